@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use mc_classic_js::{get_tile_map, serialize_saved_game_cached, ChangedBlocks, JSLevel, TileMapCache};
+
+use std::collections::HashMap;
+
+//Serializes an edited world against an already cached natural generation,
+//so only the comparison of the tile maps and changedBlocks is measured
+fn bench_serialize (c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialize_saved_game");
+    group.sample_size(10);
+
+    for world_size in [256, 512] {
+        let seed: i64 = 12345;
+        let mut cache: TileMapCache = TileMapCache::new();
+        let mut tile_map: Vec<u8> = get_tile_map(world_size, seed);
+        cache.get_or_generate(world_size, seed);
+
+        //Editing every 997th block, and storing a few hundred of them as changedBlocks too
+        let mut changed_blocks: HashMap<String, ChangedBlocks> = HashMap::new();
+        for i in (0..tile_map.len()).step_by(997) {
+            tile_map[i] = 5;
+            if i % 7 == 0 {
+                let i: i32 = i as i32;
+                let key: String = format!("p{}_{}_{}", i % world_size, i / (world_size * world_size), (i / world_size) % world_size);
+                changed_blocks.insert(key, ChangedBlocks::new(1, 5));
+            }
+        }
+        let level: JSLevel = JSLevel::new(seed, changed_blocks, world_size, 1);
+
+        group.bench_with_input(BenchmarkId::from_parameter(world_size), &world_size, |b, _| {
+            b.iter(|| serialize_saved_game_cached(level.clone(), tile_map.clone(), 1, &mut cache).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_serialize);
+criterion_main!(benches);
@@ -0,0 +1,201 @@
+/*!
+ * Checking a Firefox localStorage store against what Firefox expects,
+ * for finding out why a written store doesn't load in the browser
+ */
+
+use rusqlite::{Connection, OpenFlags};
+
+use std::fs;
+use std::path::Path;
+
+use crate::firefox::{decode_local_storage, local_storage_from_row, stored_usage};
+use crate::{origin_to_dir, read_metadata_v2, ClassicJsError, Codec, LocalStorage, MetadataV2, Origin};
+
+/**
+ * Severity enum stores how serious a finding of diagnose_store is
+ * Error: Firefox will not load the store, or will load it wrong
+ * Warning: Firefox loads the store, but it differs from what Firefox writes
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+/**
+ * Diagnostic struct stores a single finding of diagnose_store
+ * severity: How serious the finding is
+ * message: What is wrong, naming the file, table or pragma involved
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String
+}
+
+impl Diagnostic {
+    pub fn new (severity: Severity, message: String) -> Self {
+        Diagnostic { severity, message }
+    }
+}
+
+//Columns of the tables Firefox creates in data.sqlite
+const DATA_COLUMNS: [&str; 6] = ["key", "utf16_length", "conversion_type", "compression_type", "last_access_time", "value"];
+const DATABASE_COLUMNS: [&str; 5] = ["origin", "usage", "last_vacuum_time", "last_analyze_time", "last_vacuum_size"];
+
+//Cookie Firefox starts the ls/usage file with
+const USAGE_FILE_COOKIE: u32 = 0x420a420a;
+
+/**
+ * Following function checks the store of a website in the folder
+ * write_data writes into, and returns everything found wrong with it:
+ * missing files, the data and database tables and their columns, the
+ * utf16_length of each value, the user_version, auto_vacuum and page_size
+ * pragmas, the .metadata-v2
+ * file and the ls/usage file. An empty list means nothing was found
+ */
+pub fn diagnose_store (base_path: String, website: String) -> Vec<Diagnostic> {
+
+    let mut findings: Vec<Diagnostic> = Vec::new();
+
+    let dir: String = base_path + "/" + &origin_to_dir(&website);
+    let metadata_path: String = dir.clone() + "/.metadata-v2";
+    let db_path: String = dir.clone() + "/ls/data.sqlite";
+    let usage_path: String = dir.clone() + "/ls/usage";
+
+    if !Path::new(&dir).is_dir() {
+        findings.push(Diagnostic::new(Severity::Error, format!("{} does not exist", dir)));
+        return findings;
+    }
+    for path in [&metadata_path, &db_path, &usage_path] {
+        if !Path::new(path).is_file() { findings.push(Diagnostic::new(Severity::Error, format!("{} does not exist", path))) }
+    }
+
+    //Metadata
+    match read_metadata_v2(metadata_path.clone()) {
+        Ok(metadata) => check_metadata(&metadata, &website, &mut findings),
+        Err(e) => findings.push(Diagnostic::new(Severity::Error, format!("{} could not be read: {}", metadata_path, e)))
+    }
+
+    //Database
+    let conn: Connection = match Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY) {
+        Ok(conn) => conn,
+        Err(e) => {
+            findings.push(Diagnostic::new(Severity::Error, format!("{} could not be opened: {}", db_path, e)));
+            return findings;
+        }
+    };
+    check_database(&conn, &website, &mut findings);
+
+    //Usage
+    if let Ok(usage) = fs::read(&usage_path) { check_usage(&usage, &usage_path, &mut findings) }
+
+    return findings;
+
+}
+
+/**
+ * Following function runs diagnose_store and returns whether Firefox will
+ * load the store, which is when none of the findings are errors. Warnings
+ * are differences Firefox accepts, such as the empty usage file write_data
+ * leaves. An error is returned if website is not a valid origin
+ */
+pub fn will_firefox_load (base_path: String, website: String) -> Result<bool, ClassicJsError> {
+
+    let website: Origin = Origin::parse(&website)?;
+
+    let findings: Vec<Diagnostic> = diagnose_store(base_path, website.to_string());
+    return Ok(findings.iter().all(|finding| finding.severity != Severity::Error));
+
+}
+
+/**
+ * Following function checks the origin stored in .metadata-v2
+ */
+fn check_metadata (metadata: &MetadataV2, website: &str, findings: &mut Vec<Diagnostic>) {
+    if metadata.origin != website {
+        findings.push(Diagnostic::new(Severity::Error, format!("origin in .metadata-v2 is {}, not {}", metadata.origin, website)));
+    }
+}
+
+/**
+ * Following function checks the tables and pragmas of data.sqlite
+ */
+fn check_database (conn: &Connection, website: &str, findings: &mut Vec<Diagnostic>) {
+
+    for (table, columns) in [("data", &DATA_COLUMNS[..]), ("database", &DATABASE_COLUMNS[..])] {
+        let found: Vec<String> = match conn.prepare(&format!("PRAGMA table_info({})", table))
+            .and_then(|mut stmt| stmt.query_map([], |row| row.get::<_, String>(1))?.collect()) {
+            Ok(found) => found,
+            Err(e) => {
+                findings.push(Diagnostic::new(Severity::Error, format!("{} table could not be read: {}", table, e)));
+                continue;
+            }
+        };
+
+        if found.is_empty() {
+            findings.push(Diagnostic::new(Severity::Error, format!("{} table does not exist", table)));
+        } else if found != columns {
+            findings.push(Diagnostic::new(Severity::Error, format!("{} table has columns {:?}, expected {:?}", table, found, columns)));
+        }
+    }
+
+    //Pragmas, Firefox refuses databases with another schema version
+    let pragma = |name: &str| conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get::<_, i64>(0));
+    for (name, expected, severity) in [("user_version", 80, Severity::Error), ("auto_vacuum", 2, Severity::Warning), ("page_size", 1024, Severity::Warning)] {
+        match pragma(name) {
+            Ok(value) if value == expected => (),
+            Ok(value) => findings.push(Diagnostic::new(severity, format!("{} is {}, expected {}", name, value, expected))),
+            Err(e) => findings.push(Diagnostic::new(severity, format!("{} could not be read: {}", name, e)))
+        }
+    }
+
+    //Firefox sizes the buffer it reads a value into by its utf16_length,
+    //so a length counting characters instead of UTF-16 code units is too short
+    if let Ok(mut stmt) = conn.prepare("SELECT * FROM data") {
+        let mut codec: Codec = Codec::new();
+        let rows: Vec<LocalStorage> = stmt.query_map([], local_storage_from_row)
+            .map(|rows| rows.filter_map(Result::ok).collect())
+            .unwrap_or_default();
+
+        for local in rows {
+            match decode_local_storage(&local, &mut codec).map(|value| value.encode_utf16().count() as i64) {
+                Ok(len) if len != local.utf16_length as i64 => {
+                    findings.push(Diagnostic::new(Severity::Error, format!("utf16_length of {} is {}, but its value is {} UTF-16 code units", local.key, local.utf16_length, len)));
+                },
+                Ok(_) => (),
+                Err(e) => findings.push(Diagnostic::new(Severity::Error, format!("value of {} could not be decoded: {}", local.key, e)))
+            }
+        }
+    }
+
+    //The database row, whose usage should match the stored values
+    let row: Result<(String, i64), rusqlite::Error> = conn.query_row("SELECT origin, usage FROM database", [], |row| Ok((row.get(0)?, row.get(1)?)));
+    let len: Result<i64, ClassicJsError> = stored_usage(conn);
+    match (row, len) {
+        (Ok((origin, _)), _) if origin != website => {
+            findings.push(Diagnostic::new(Severity::Error, format!("origin in the database table is {}, not {}", origin, website)));
+        },
+        (Ok((_, usage)), Ok(len)) if usage != len => {
+            findings.push(Diagnostic::new(Severity::Warning, format!("usage in the database table is {}, but the stored keys and values add up to {}", usage, len)));
+        },
+        (Err(rusqlite::Error::QueryReturnedNoRows), _) => {
+            findings.push(Diagnostic::new(Severity::Error, String::from("database table has no row")));
+        },
+        _ => ()
+    }
+
+}
+
+/**
+ * Following function checks the ls/usage file. write_data leaves it empty,
+ * which Firefox accepts and recomputes, otherwise it is a u32 cookie
+ * followed by the u64 usage
+ */
+fn check_usage (usage: &[u8], usage_path: &str, findings: &mut Vec<Diagnostic>) {
+    if usage.is_empty() {
+        findings.push(Diagnostic::new(Severity::Warning, format!("{} is empty, Firefox will recompute the usage", usage_path)));
+    } else if usage.len() != 12 || u32::from_be_bytes([usage[0], usage[1], usage[2], usage[3]]) != USAGE_FILE_COOKIE {
+        findings.push(Diagnostic::new(Severity::Error, format!("{} is not a usage file Firefox can read", usage_path)));
+    }
+}
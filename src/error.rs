@@ -0,0 +1,96 @@
+use std::fmt;
+
+/**
+ * ClassicJsError is the error type returned by the fallible functions
+ * of this crate. It wraps the errors of the libraries used underneath
+ * (sqlite, serde_json, snappy and io), as well as errors specific to
+ * classic js stores
+ */
+#[derive(Debug)]
+pub enum ClassicJsError {
+    Io(std::io::Error),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+    Json(serde_json::Error),
+    Snappy(snap::Error),
+    LockTimeout(String),
+    MissingKey(String),
+    InvalidWorldSize(i32),
+    TooManyChangedBlocks(usize, usize),
+    InvalidUtf8(String),
+    InvalidUtf16(String),
+    UnsupportedFormat(String),
+    StoreExists(String),
+    OutsideBasePath(String),
+    UnsupportedCompression(i32),
+    InvalidMetadata(String),
+    UnsupportedMigration(u8, u8),
+    TileMapSizeMismatch(usize, usize),
+    PragmaNotApplied(String, i64),
+    InvalidOrigin(String),
+    EmptyValue(String),
+    InvalidCommand(String),
+    InvalidRle(String),
+    NotAClassicStore(String)
+}
+
+impl fmt::Display for ClassicJsError {
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClassicJsError::Io(e) => write!(f, "io error: {}", e),
+            #[cfg(feature = "sqlite")]
+            ClassicJsError::Sqlite(e) => write!(f, "sqlite error: {}", e),
+            ClassicJsError::Json(e) => write!(f, "json error: {}", e),
+            ClassicJsError::Snappy(e) => write!(f, "snappy error: {}", e),
+            ClassicJsError::LockTimeout(path) => write!(f, "timed out waiting for lock on {}", path),
+            ClassicJsError::MissingKey(key) => write!(f, "key {} is not stored in the database", key),
+            ClassicJsError::InvalidWorldSize(size) => write!(f, "world size {} is not one of 128, 256 or 512", size),
+            ClassicJsError::TooManyChangedBlocks(count, max) => write!(f, "save has {} changed blocks, more than the limit of {}", count, max),
+            ClassicJsError::InvalidUtf8(key) => write!(f, "value of {} is not valid UTF-8", key),
+            ClassicJsError::InvalidUtf16(key) => write!(f, "value of {} is not valid UTF-16", key),
+            ClassicJsError::UnsupportedFormat(format) => write!(f, "cannot read localStorage stored as {}", format),
+            ClassicJsError::StoreExists(path) => write!(f, "{} already exists and overwriting was not enabled", path),
+            ClassicJsError::OutsideBasePath(path) => write!(f, "{} is outside of the base path", path),
+            ClassicJsError::UnsupportedCompression(compression_type) => write!(f, "compression_type {} is not supported", compression_type),
+            ClassicJsError::InvalidMetadata(path) => write!(f, "{} is not a valid .metadata-v2 file", path),
+            ClassicJsError::UnsupportedMigration(from, to) => write!(f, "cannot migrate a savedGame from version {} to version {}", from, to),
+            ClassicJsError::TileMapSizeMismatch(expected, len) => write!(f, "tile map holds {} blocks, but the world needs {}", len, expected),
+            ClassicJsError::PragmaNotApplied(pragma, value) => write!(f, "pragma {} did not apply, it is still {}, VACUUMing the store (e.g. with vacuum_store) applies it", pragma, value),
+            ClassicJsError::InvalidOrigin(website) => write!(f, "{} is not an origin in the scheme://host[:port] form", website),
+            ClassicJsError::EmptyValue(key) => write!(f, "value of {} is empty, but its compression_type says it is compressed", key),
+            ClassicJsError::InvalidCommand(reason) => write!(f, "not a localStorage.setItem() command: {}", reason),
+            ClassicJsError::InvalidRle(reason) => write!(f, "invalid run-length encoding: {}", reason),
+            ClassicJsError::NotAClassicStore(path) => write!(f, "{} is not a localStorage store, it has no data table", path)
+        }
+    }
+}
+
+impl std::error::Error for ClassicJsError {
+    fn source (&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClassicJsError::Io(e) => Some(e),
+            #[cfg(feature = "sqlite")]
+            ClassicJsError::Sqlite(e) => Some(e),
+            ClassicJsError::Json(e) => Some(e),
+            ClassicJsError::Snappy(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for ClassicJsError {
+    fn from (e: std::io::Error) -> Self { ClassicJsError::Io(e) }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for ClassicJsError {
+    fn from (e: rusqlite::Error) -> Self { ClassicJsError::Sqlite(e) }
+}
+
+impl From<serde_json::Error> for ClassicJsError {
+    fn from (e: serde_json::Error) -> Self { ClassicJsError::Json(e) }
+}
+
+impl From<snap::Error> for ClassicJsError {
+    fn from (e: snap::Error) -> Self { ClassicJsError::Snappy(e) }
+}
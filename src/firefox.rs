@@ -0,0 +1,1128 @@
+/*!
+ * Reading and writing Firefox localStorage stores, the ls/data.sqlite
+ * databases and the files Firefox keeps alongside them
+ */
+
+use fs2::FileExt;
+
+use rusqlite::{Connection, DatabaseName, ErrorCode, OpenFlags};
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{
+    compress_value_with, decompress_value_with, origin_to_dir,
+    serialize_saved_game_sorted, serialize_settings,
+    ClassicJsError, Codec, Compression, Data, JSLevel, LocalStorage, LocalStorageEntry,
+    Origin, Settings, WriteOptions, MAX_CHANGED_BLOCKS, WORLD_SIZES
+};
+
+/**
+ * How long write_data will wait to acquire the lock on an
+ * origin's ls directory before giving up
+ */
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/**
+ * Following function converts a row of the data table into LocalStorage
+ */
+pub(crate) fn local_storage_from_row (row: &rusqlite::Row) -> rusqlite::Result<LocalStorage> {
+    Ok(
+        LocalStorage {
+            key: row.get(0)?,
+            utf16_length: row.get(1)?,
+            conversion_type: row.get(2)?,
+            compression_type: row.get(3)?,
+            last_access_time: row.get(4)?,
+            value: row.get::<_, Option<Vec<u8>>>(5)?.unwrap_or_default(), //A NULL value is read as empty
+        }
+    )
+}
+
+/**
+ * Following function opens the sqlite database at the provided path as a
+ * localStorage store. NotAClassicStore is returned if the file isn't an
+ * sqlite database or has no data table, e.g. an empty or unrelated file,
+ * rather than the sqlite error for the first query. A gzipped data.sqlite,
+ * as stores are often shared, is decompressed into memory and opened read
+ * only, which needs the gzip feature
+ */
+pub(crate) fn open_store (file_path: &str) -> Result<Connection, ClassicJsError> {
+
+    let conn: Connection = match is_gzip(file_path) {
+        true => open_gzip_store(file_path)?,
+        false => open_existing(file_path)?
+    };
+
+    let has_data: Result<bool, rusqlite::Error> = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'data')", [], |row| row.get(0)
+    );
+
+    return match has_data {
+        Ok(true) => Ok(conn),
+        Ok(false) => Err(ClassicJsError::NotAClassicStore(String::from(file_path))),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::NotADatabase => Err(ClassicJsError::NotAClassicStore(String::from(file_path))),
+        Err(e) => Err(ClassicJsError::Sqlite(e))
+    };
+
+}
+
+/**
+ * Following function opens an sqlite database for reading and writing
+ * without creating it, so a mistyped path doesn't leave a blank database
+ * behind. A missing file is returned as a NotFound io error
+ */
+fn open_existing (file_path: &str) -> Result<Connection, ClassicJsError> {
+
+    let flags: OpenFlags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+
+    return match Connection::open_with_flags(file_path, flags) {
+        Ok(conn) => Ok(conn),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::CannotOpen => {
+            Err(ClassicJsError::Io(std::io::Error::new(ErrorKind::NotFound, format!("{} could not be opened", file_path))))
+        },
+        Err(e) => Err(ClassicJsError::Sqlite(e))
+    };
+
+}
+
+//Magic bytes every gzip file starts with
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/**
+ * Following function checks whether the file at the path starts with the
+ * gzip magic bytes. Files that can't be read are left for sqlite to report
+ */
+fn is_gzip (file_path: &str) -> bool {
+    let mut magic: [u8; 2] = [0; 2];
+    return match File::open(file_path).and_then(|mut file| file.read_exact(&mut magic)) {
+        Ok(()) => magic == GZIP_MAGIC,
+        Err(_) => false
+    };
+}
+
+/**
+ * Following function decompresses a gzipped sqlite database into a read
+ * only database in memory
+ */
+#[cfg(feature = "gzip")]
+fn open_gzip_store (file_path: &str) -> Result<Connection, ClassicJsError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    flate2::read::GzDecoder::new(File::open(file_path)?).read_to_end(&mut bytes)?;
+
+    //Databases in memory can't be in WAL mode, which Firefox uses, so the
+    //read and write versions of the header are set back to the rollback journal
+    if bytes.len() >= 20 && bytes[18] == 2 && bytes[19] == 2 {
+        bytes[18] = 1;
+        bytes[19] = 1;
+    }
+
+    let mut conn: Connection = Connection::open_in_memory()?;
+    let len: usize = bytes.len();
+    conn.deserialize_read_exact(DatabaseName::Main, &bytes[..], len, true)?;
+
+    return Ok(conn);
+}
+
+#[cfg(not(feature = "gzip"))]
+fn open_gzip_store (file_path: &str) -> Result<Connection, ClassicJsError> {
+    return Err(ClassicJsError::UnsupportedFormat(format!("gzip compressed {} without the gzip feature", file_path)));
+}
+
+/**
+ * Following function retreives the row of the specified object from an
+ * already opened localStorage database, without decompressing it.
+ * None is returned if the object is not stored in the database
+ */
+fn query_local_storage (conn: &Connection, object: &str) -> Result<Option<LocalStorage>, ClassicJsError> {
+
+    let mut stmt = conn.prepare(
+        "SELECT * FROM data where key=?1;"
+    )?;
+
+    //Iterating through the database
+    let mut entries = stmt.query_map([object], local_storage_from_row)?;
+
+    return match entries.next() {
+        Some(entry) => Ok(Some(entry?)),
+        None => Ok(None)
+    };
+
+}
+
+/**
+ * Following function retreives the row of an object from an already
+ * opened localStorage database, ignoring the case of the key, e.g.
+ * Settings or SETTINGS for settings. Only ASCII letters are folded. If
+ * several keys match, the first in sqlite's binary order is taken
+ */
+fn query_local_storage_nocase (conn: &Connection, object: &str) -> Result<Option<LocalStorage>, ClassicJsError> {
+
+    let mut stmt = conn.prepare(
+        "SELECT * FROM data where key=?1 COLLATE NOCASE ORDER BY key;"
+    )?;
+
+    let mut entries = stmt.query_map([object], local_storage_from_row)?;
+
+    return match entries.next() {
+        Some(entry) => Ok(Some(entry?)),
+        None => Ok(None)
+    };
+
+}
+
+/**
+ * Following function retreives the settings row from an already opened
+ * localStorage database, under the exact key given, e.g. settings, or if
+ * that is not stored, under any casing of it, e.g. Settings
+ */
+fn query_settings (conn: &Connection, key: &str) -> Result<Option<LocalStorage>, ClassicJsError> {
+
+    return match query_local_storage(conn, key)? {
+        Some(local) => Ok(Some(local)),
+        None => query_local_storage_nocase(conn, key)
+    };
+
+}
+
+/**
+ * Following function decompresses the value of a localStorage row
+ * with the given codec, leaving it as the stored bytes
+ */
+fn decompress_local_storage (local: &LocalStorage, codec: &mut Codec) -> Result<Vec<u8>, ClassicJsError> {
+
+    let compression: Compression = match Compression::from_compression_type(local.compression_type) {
+        Some(compression) => compression,
+        None => return Err(ClassicJsError::UnsupportedCompression(local.compression_type))
+    };
+
+    //An uncompressed empty value is an empty string, but a compressed value
+    //always has at least the length header, so an empty one is corrupt
+    if local.value.is_empty() {
+        return match compression {
+            Compression::None => Ok(Vec::new()),
+            _ => Err(ClassicJsError::EmptyValue(local.key.clone()))
+        };
+    }
+
+    return decompress_value_with(&local.value, compression, codec);
+
+}
+
+/**
+ * Following function decompresses the value of a localStorage row
+ * with the given codec and converts it into a string
+ */
+pub(crate) fn decode_local_storage (local: &LocalStorage, codec: &mut Codec) -> Result<String, ClassicJsError> {
+
+    //Decompressing into a single buffer, which becomes the string without another copy
+    let decompressed: Vec<u8> = decompress_local_storage(local, codec)?;
+
+    //Firefox stores values either converted to UTF-8 or as UTF-16LE code units
+    return match local.conversion_type {
+        1 => match String::from_utf8(decompressed) {
+            Ok(string) => Ok(string),
+            Err(_) => Err(ClassicJsError::InvalidUtf8(local.key.clone()))
+        },
+        0 => {
+            let units = decompressed.chunks_exact(2);
+            if !units.remainder().is_empty() { return Err(ClassicJsError::InvalidUtf16(local.key.clone())) }
+
+            //Decoding the code units straight into the string, without collecting them first
+            let mut string: String = String::with_capacity(decompressed.len() / 2);
+            for c in char::decode_utf16(units.map(|unit| u16::from_le_bytes([unit[0], unit[1]]))) {
+                match c {
+                    Ok(c) => string.push(c),
+                    Err(_) => return Err(ClassicJsError::InvalidUtf16(local.key.clone()))
+                }
+            }
+            Ok(string)
+        },
+        conversion_type => Err(ClassicJsError::UnsupportedFormat(format!("conversion_type {}", conversion_type)))
+    };
+
+}
+
+/**
+ * Following function retreives the specified object from an
+ * already opened localStorage database and decompresses it.
+ * None is returned if the object is not stored in the database
+ */
+pub(crate) fn read_from_conn (conn: &Connection, object: &str) -> Result<Option<String>, ClassicJsError> {
+
+    return match query_local_storage(conn, object)? {
+        Some(local) => Ok(Some(decode_local_storage(&local, &mut Codec::new())?)),
+        None => Ok(None)
+    };
+
+}
+
+/**
+ * Following function opens an sqlite database at the provided path,
+ * then retreives the specified object, and then decompresses it 
+ * before returning it. An empty string is returned if the object
+ * is not stored in the database
+ */
+pub fn read_from_db (file_path: String, object: &str) -> Result<String, ClassicJsError> {
+
+    let conn: Connection = open_store(&file_path)?;
+
+    return Ok(read_from_conn(&conn, object)?.unwrap_or_default());
+
+}
+
+/**
+ * Following function opens an sqlite database at the provided path,
+ * then retreives the savedGame and hashes its decompressed bytes with
+ * 64 bit FNV-1a, without decoding them into a string. Two stores holding
+ * the same savedGame bytes give the same digest, across runs and versions
+ * of this crate, so digests can be compared to find stores of the same
+ * world. A savedGame stored as UTF-16 hashes differently from the same one
+ * stored as UTF-8
+ */
+pub fn saved_game_digest (file_path: String) -> Result<u64, ClassicJsError> {
+
+    let conn: Connection = open_store(&file_path)?;
+
+    let local: LocalStorage = match query_local_storage(&conn, "savedGame")? {
+        Some(local) => local,
+        None => return Err(ClassicJsError::MissingKey(String::from("savedGame")))
+    };
+
+    let mut digest: u64 = FNV_OFFSET_BASIS;
+    for byte in decompress_local_storage(&local, &mut Codec::new())? {
+        digest ^= byte as u64;
+        digest = digest.wrapping_mul(FNV_PRIME);
+    }
+
+    return Ok(digest);
+
+}
+
+//Constants of 64 bit FNV-1a, used by saved_game_digest
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/**
+ * Following function opens an sqlite database at the provided path,
+ * then retreives every row of the data table with a single query and
+ * decompresses each of them, returning a map of key to decoded value
+ */
+pub fn read_all (file_path: String) -> Result<HashMap<String, String>, ClassicJsError> {
+
+    let conn: Connection = open_store(&file_path)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT * FROM data;"
+    )?;
+
+    //Iterating through the database
+    let entries = stmt.query_map([], local_storage_from_row)?;
+
+    //One codec is reused for every row
+    let mut codec: Codec = Codec::new();
+    let mut values: HashMap<String, String> = HashMap::new();
+    for entry in entries {
+        let local: LocalStorage = entry?;
+        let value: String = decode_local_storage(&local, &mut codec)?;
+        values.insert(local.key, value);
+    }
+
+    return Ok(values);
+
+}
+
+/**
+ * Following function opens an sqlite database at the provided path,
+ * then retreives the specified object and decompresses it, returning
+ * it along with the length, compression and last access time stored
+ * in its row. None is returned if the object is not stored in the database
+ */
+pub fn read_entry (file_path: String, object: &str) -> Result<Option<LocalStorageEntry>, ClassicJsError> {
+
+    let conn: Connection = open_store(&file_path)?;
+
+    let local: LocalStorage = match query_local_storage(&conn, object)? {
+        Some(local) => local,
+        None => return Ok(None)
+    };
+
+    return Ok(Some(LocalStorageEntry {
+        value: decode_local_storage(&local, &mut Codec::new())?,
+        utf16_length: local.utf16_length,
+        compression_type: local.compression_type,
+        last_access_time: local.last_access_time
+    }));
+
+}
+
+/**
+ * Following function opens an sqlite database at the provided path,
+ * then retreives the row of the specified object exactly as it is
+ * stored, with the value still compressed. None is returned if the
+ * object is not stored in the database
+ */
+pub fn read_raw (file_path: String, object: &str) -> Result<Option<LocalStorage>, ClassicJsError> {
+
+    let conn: Connection = open_store(&file_path)?;
+
+    return query_local_storage(&conn, object);
+
+}
+
+/**
+ * Following function opens an sqlite database at the provided path,
+ * then retreives the specified object, and then decompresses it 
+ * before returning it
+ */
+pub fn read_saved_game (file_path: String) -> Result<String, ClassicJsError> {
+    return read_from_db(file_path, "savedGame");
+}
+
+/**
+ * Following function opens an sqlite database at the provided path,
+ * then retreives the settings, and then decompresses them before
+ * returning them. The exact key settings is tried first, and if it is
+ * not stored, any casing of it, such as Settings, as some versions of
+ * the game store it under. An empty string is returned if neither is
+ * stored in the database
+ */
+pub fn read_settings (file_path: String) -> Result<String, ClassicJsError> {
+
+    let conn: Connection = open_store(&file_path)?;
+
+    return match query_settings(&conn, "settings")? {
+        Some(local) => Ok(decode_local_storage(&local, &mut Codec::new())?),
+        None => Ok(String::new())
+    };
+
+}
+
+/**
+ * Following function retreives a savedGame and settings from an already
+ * opened localStorage database with a single query and decompresses them.
+ * If the settings key is not stored exactly, any casing of it is tried,
+ * as read_settings does. Each is None if it is not stored in the database
+ */
+fn read_pair_from_conn (conn: &Connection, keys: [&str; 2]) -> Result<(Option<String>, Option<String>), ClassicJsError> {
+
+    let mut stmt = conn.prepare(
+        "SELECT * FROM data WHERE key IN (?1, ?2);"
+    )?;
+    let entries = stmt.query_map(keys, local_storage_from_row)?;
+
+    let mut codec: Codec = Codec::new();
+    let mut values: (Option<String>, Option<String>) = (None, None);
+    for entry in entries {
+        let local: LocalStorage = entry?;
+        let value: String = decode_local_storage(&local, &mut codec)?;
+        if local.key == keys[0] { values.0 = Some(value) } else { values.1 = Some(value) }
+    }
+
+    if values.1.is_none() {
+        if let Some(local) = query_settings(conn, keys[1])? {
+            values.1 = Some(decode_local_storage(&local, &mut codec)?);
+        }
+    }
+
+    return Ok(values);
+
+}
+
+/**
+ * Following function opens an sqlite database at the provided path,
+ * then retreives both the savedGame and settings with a single query
+ * and decompresses them. The settings are read from the key settings, or
+ * if that is not stored, any casing of it, such as Settings. Each is None
+ * if it is not stored in the database
+ */
+pub fn read_saved_game_and_settings (file_path: String) -> Result<(Option<String>, Option<String>), ClassicJsError> {
+
+    let conn: Connection = open_store(&file_path)?;
+
+    return read_pair_from_conn(&conn, ["savedGame", "settings"]);
+
+}
+
+/**
+ * Following function opens an sqlite database at the provided path,
+ * then retreives both the savedGame and settings and deserializes them
+ * into a Data struct. The settings are read from the key settings, or
+ * if that is not stored, any casing of it, such as Settings. If neither
+ * is stored in the database, the default settings are used instead
+ */
+pub fn read_data (file_path: String) -> Result<Data, ClassicJsError> {
+    return read_data_with_keys(file_path, ["savedGame", "settings"]);
+}
+
+/**
+ * Same as read_data, but the savedGame and settings are read from the
+ * given keys, e.g. a slot written with WriteOptions::keys. As with
+ * read_data, any casing of the settings key is tried if it is not
+ * stored exactly
+ */
+pub fn read_data_with_keys (file_path: String, keys: [&str; 2]) -> Result<Data, ClassicJsError> {
+
+    let conn: Connection = open_store(&file_path)?;
+
+    let (level_str, settings_str): (Option<String>, Option<String>) = read_pair_from_conn(&conn, keys)?;
+
+    let level_str: String = match level_str {
+        Some(level_str) => level_str,
+        None => return Err(ClassicJsError::MissingKey(String::from(keys[0])))
+    };
+    let level: JSLevel = serde_json::from_str(&level_str)?;
+
+    let settings: Settings = match settings_str {
+        Some(settings_str) => serde_json::from_str(&settings_str)?,
+        None => Settings::default()
+    };
+
+    return Ok(Data::new(level, settings));
+
+}
+
+/**
+ * Following function returns the group Firefox counts an origin's quota
+ * against, which is the base domain of the origin. The base domain is
+ * taken as the last two labels of the host, so origins under multi-label
+ * public suffixes such as .co.uk get one label too few
+ */
+fn origin_group (website: &Origin) -> String {
+    let host: &str = website.host();
+
+    //IP addresses and single label hosts such as localhost are their own group
+    if host.parse::<std::net::Ipv4Addr>().is_ok() || host.starts_with('[') || !host.contains('.') { return host.to_string() }
+
+    let labels: Vec<&str> = host.split('.').collect();
+    return labels[labels.len() - 2..].join(".");
+}
+
+/**
+ * Following function builds the .metadata-v2 file Firefox keeps
+ * in each origin's storage folder, in the layout of Firefox's
+ * CreateDirectoryMetadata2: timestamp, persisted, two reserved u32s,
+ * then the suffix, group and origin strings and the is app flag
+ */
+fn build_metadata_v2 (website: &Origin, timestamp: u64) -> Vec<u8> {
+    let mut metadata: Vec<u8> = Vec::new();
+    metadata.extend_from_slice(&timestamp.to_be_bytes()); //Timestamp
+    metadata.push(0); //Persisted
+    metadata.extend_from_slice(&0_u32.to_be_bytes()); //Reserved data 1
+    metadata.extend_from_slice(&0_u32.to_be_bytes()); //Reserved data 2
+
+    //Strings are written by Firefox with a u32 length and no terminator
+    for string in ["", &origin_group(website), website.as_str()] { //Suffix, Group, Origin
+        metadata.extend_from_slice(&(string.len() as u32).to_be_bytes());
+        metadata.extend_from_slice(string.as_bytes());
+    }
+
+    metadata.push(0); //Is App
+
+    return metadata;
+}
+
+/**
+ * MetadataV2 struct stores the contents of the .metadata-v2 file Firefox
+ * keeps in the storage folder of each origin
+ * timestamp: Microseconds since the unix epoch the origin was last accessed
+ * persisted: Whether the origin's storage is persistent
+ * suffix: Origin attributes suffix, empty for normal browsing
+ * group: Group the origin's quota is counted against
+ * origin: The origin itself, e.g. https://classic.minecraft.net
+ * is_app: Unused by Firefox, always false
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataV2 {
+    pub timestamp: u64,
+    pub persisted: bool,
+    pub suffix: String,
+    pub group: String,
+    pub origin: String,
+    pub is_app: bool
+}
+
+/**
+ * Following function takes the next count bytes of a .metadata-v2 file,
+ * failing if the file ends before them
+ */
+fn take_bytes<'a> (bytes: &'a [u8], pos: &mut usize, count: usize, path: &str) -> Result<&'a [u8], ClassicJsError> {
+    if bytes.len() < *pos + count { return Err(ClassicJsError::InvalidMetadata(path.to_string())) }
+
+    let taken: &[u8] = &bytes[*pos..*pos + count];
+    *pos += count;
+
+    return Ok(taken);
+}
+
+/**
+ * Following function reads a string prefixed by its u32 length
+ * from a .metadata-v2 file
+ */
+fn take_string (bytes: &[u8], pos: &mut usize, path: &str) -> Result<String, ClassicJsError> {
+    let len: usize = u32::from_be_bytes(take_bytes(bytes, pos, 4, path)?.try_into().unwrap()) as usize;
+
+    return match String::from_utf8(take_bytes(bytes, pos, len, path)?.to_vec()) {
+        Ok(string) => Ok(string),
+        Err(_) => Err(ClassicJsError::InvalidMetadata(path.to_string()))
+    };
+}
+
+/**
+ * Following function reads a .metadata-v2 file, as written by
+ * Firefox or write_data, back into MetadataV2
+ */
+pub fn read_metadata_v2 (path: String) -> Result<MetadataV2, ClassicJsError> {
+
+    let bytes: Vec<u8> = fs::read(&path)?;
+    let mut pos: usize = 0;
+
+    let timestamp: u64 = u64::from_be_bytes(take_bytes(&bytes, &mut pos, 8, &path)?.try_into().unwrap());
+    let persisted: bool = take_bytes(&bytes, &mut pos, 1, &path)?[0] != 0;
+    take_bytes(&bytes, &mut pos, 8, &path)?; //Reserved data 1 and 2
+    let suffix: String = take_string(&bytes, &mut pos, &path)?;
+    let group: String = take_string(&bytes, &mut pos, &path)?;
+    let origin: String = take_string(&bytes, &mut pos, &path)?;
+    let is_app: bool = take_bytes(&bytes, &mut pos, 1, &path)?[0] != 0;
+
+    return Ok(MetadataV2 { timestamp, persisted, suffix, group, origin, is_app });
+
+}
+
+/**
+ * Following function computes the usage of the rows stored in an opened
+ * localStorage database, the same way compute_usage does, from the keys
+ * and the utf16_length of their values
+ */
+pub(crate) fn stored_usage (conn: &Connection) -> Result<i64, ClassicJsError> {
+    let mut stmt = conn.prepare("SELECT key, utf16_length FROM data")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?;
+
+    let mut usage: i64 = 0;
+    for row in rows {
+        let (key, utf16_length) = row?;
+        usage += key.encode_utf16().count() as i64 + utf16_length;
+    }
+
+    return Ok(usage);
+}
+
+/**
+ * Following function creates the database table Firefox uses to track
+ * an origin's usage if it does not exist, and replaces its row
+ */
+fn write_database_table (conn: &Connection, website: &str, usage: i64, last_vacuum_time: u64, vacuum_size: u64) -> Result<(), ClassicJsError> {
+    conn.execute(
+        "CREATE TABLE if not exists database ( 
+        origin TEXT NOT NULL, 
+        usage INTEGER NOT NULL DEFAULT 0, 
+        last_vacuum_time INTEGER NOT NULL DEFAULT 0, 
+        last_analyze_time INTEGER NOT NULL DEFAULT 0, 
+        last_vacuum_size INTEGER NOT NULL DEFAULT 0)",
+        [])?;
+
+    //The table has no key to replace on, so the old row is cleared first
+    conn.execute("DELETE FROM database", [])?;
+
+    let mut stmt = conn.prepare("INSERT INTO database (origin,usage,last_vacuum_time,last_analyze_time,last_vacuum_size) values (?1, ?2, ?3, ?4, ?5)" )?;
+
+    stmt.execute((website,usage,last_vacuum_time,0,vacuum_size))?;
+
+    return Ok(());
+}
+
+/**
+ * DatabaseMeta struct stores the row of the database table Firefox keeps
+ * in data.sqlite to track an origin's usage
+ * origin: The origin the store belongs to, e.g. https://classic.minecraft.net
+ * usage: Usage of the stored items, see compute_usage
+ * last_vacuum_time: Microseconds since the unix epoch the database was last vacuumed
+ * last_analyze_time: Microseconds since the unix epoch the database was last analyzed
+ * last_vacuum_size: Size of the database file in bytes after the last vacuum
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseMeta {
+    pub origin: String,
+    pub usage: i64,
+    pub last_vacuum_time: i64,
+    pub last_analyze_time: i64,
+    pub last_vacuum_size: i64
+}
+
+/**
+ * Following function opens an sqlite database at the provided path and
+ * reads back the row of its database table, as write_data writes it.
+ * Like the other readers, a missing file is not created, and a file that
+ * isn't a store gives NotAClassicStore
+ */
+pub fn read_database_meta (file_path: String) -> Result<DatabaseMeta, ClassicJsError> {
+
+    let conn: Connection = open_store(&file_path)?;
+
+    let meta: DatabaseMeta = conn.query_row(
+        "SELECT origin, usage, last_vacuum_time, last_analyze_time, last_vacuum_size FROM database",
+        [],
+        |row| Ok(DatabaseMeta {
+            origin: row.get(0)?,
+            usage: row.get(1)?,
+            last_vacuum_time: row.get(2)?,
+            last_analyze_time: row.get(3)?,
+            last_vacuum_size: row.get(4)?
+        })
+    )?;
+
+    return Ok(meta);
+
+}
+
+/**
+ * Following function takes an exclusive advisory lock on a lock file
+ * inside the given ls directory, retrying until LOCK_TIMEOUT has passed.
+ * The lock is held for as long as the returned file is kept open
+ */
+fn lock_store (ls_path: &str) -> Result<File, ClassicJsError> {
+    let lock_path: String = String::from(ls_path) + "/mc-classic-js.lock";
+    let lock_file: File = File::create(&lock_path)?;
+    let start: Instant = Instant::now();
+
+    while lock_file.try_lock_exclusive().is_err() {
+        if start.elapsed() >= LOCK_TIMEOUT { return Err(ClassicJsError::LockTimeout(lock_path)) }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    return Ok(lock_file);
+}
+
+/**
+ * Following function sets the page_size and auto_vacuum pragmas Firefox
+ * uses and VACUUMs the database. They only apply to a database with tables
+ * after a VACUUM, so they are set first and the VACUUM rebuilds the file
+ * with them
+ */
+fn vacuum_conn (conn: &Connection) -> Result<(), ClassicJsError> {
+    conn.pragma_update(None, "page_size", 1024)?;
+    conn.pragma_update(None, "auto_vacuum", 2)?;
+    conn.execute("VACUUM", [])?;
+
+    return Ok(());
+}
+
+/**
+ * Following function returns the size of an opened database in bytes,
+ * which works the same for databases kept in memory
+ */
+fn database_size (conn: &Connection) -> Result<u64, ClassicJsError> {
+    let page_count: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+
+    return Ok(page_count * page_size);
+}
+
+/**
+ * Following function creates the localStorage schema Firefox expects in an
+ * opened database, and inserts the savedGame and settings json strings under
+ * the given keys along with the database row for the origin. Without vacuum
+ * the pragmas are still set, which applies them to a new database, and the
+ * vacuum fields of an existing database row are kept
+ */
+fn fill_store (conn: &Connection, json_strings: &[String; 2], website: &str, options: &WriteOptions, timestamp: u64) -> Result<(), ClassicJsError> {
+
+    let keys: &[String; 2] = &options.keys;
+    let compression: Compression = options.compression;
+    let last_access_time: i64 = options.last_access_time.unwrap_or(timestamp as i64);
+
+    //Vacuum fields of the last VACUUM, read before anything is written
+    let last_vacuum: Option<(u64, u64)> = match options.vacuum {
+        true => None,
+        false => conn.query_row("SELECT last_vacuum_time, last_vacuum_size FROM database", [], |row| Ok((row.get(0)?, row.get(1)?))).ok()
+    };
+
+    if options.vacuum {
+        vacuum_conn(conn)?;
+    } else {
+        conn.pragma_update(None, "page_size", 1024)?;
+        conn.pragma_update(None, "auto_vacuum", 2)?;
+    }
+    conn.pragma_update(None, "user_version", 80)?;
+
+    //Checking the pragmas stuck, as sqlite ignores pragmas it can't apply. Without
+    //a VACUUM page_size and auto_vacuum only apply to new stores, so only
+    //user_version is checked, and existing stores keep their page layout
+    let pragmas: &[(&str, i64)] = match options.vacuum {
+        true => &[("page_size", 1024), ("auto_vacuum", 2), ("user_version", 80)],
+        false => &[("user_version", 80)]
+    };
+    for &(name, expected) in pragmas {
+        let value: i64 = conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0))?;
+        if value != expected { return Err(ClassicJsError::PragmaNotApplied(String::from(name), value)) }
+    }
+
+    //Creates the localStorage data table inside the database if it does not exist
+    conn.execute(
+        "CREATE TABLE if not exists data ( 
+        key TEXT PRIMARY KEY, 
+        utf16_length INTEGER NOT NULL, 
+        conversion_type INTEGER NOT NULL, 
+        compression_type INTEGER NOT NULL, 
+        last_access_time INTEGER NOT NULL DEFAULT 0, 
+        value BLOB NOT NULL)", 
+        []
+    )?;
+
+    //Inserting the savedGame into the database
+    let mut stmt = conn.prepare("INSERT OR REPLACE INTO data (key, utf16_length, conversion_type, compression_type, last_access_time, value) values (?1, ?2, ?3, ?4, ?5, ?6)" )?;
+
+    let mut codec: Codec = Codec::new();
+    for i in 0..json_strings.len() {
+        //Firefox measures values in UTF-16 code units, so characters outside
+        //the BMP such as emoji count twice for their surrogate pair
+        let utf16_length: i32  = json_strings[i].encode_utf16().count() as i32;
+
+        //Values are stored converted to UTF-8, conversion_type 1
+        let mut decompressed: Vec<u8> = Vec::new();
+        decompressed.extend_from_slice(json_strings[i].as_bytes());
+
+        let compressed: Vec<u8> = compress_value_with(&decompressed, compression, &mut codec)?;
+
+        stmt.execute((&keys[i], utf16_length, 1, compression.compression_type(), last_access_time, compressed))?;
+    }
+
+    //Usage covers every row, so slots already in the store are counted too
+    let usage: i64 = stored_usage(conn)?;
+
+    //A new database without a VACUUM is as compact as one, so it counts as vacuumed now
+    let (last_vacuum_time, vacuum_size): (u64, u64) = match last_vacuum {
+        Some(last_vacuum) => last_vacuum,
+        None => (timestamp, database_size(conn)?)
+    };
+
+    write_database_table(conn, website, usage, last_vacuum_time, vacuum_size)?;
+
+    return Ok(());
+
+}
+
+/**
+ * Following function opens an sqlite database at the provided path and
+ * VACUUMs it with the page_size and auto_vacuum Firefox uses, updating the
+ * last_vacuum_time and last_vacuum_size of its database table. For stores
+ * written with WriteOptions::vacuum off, to compact them when convenient
+ */
+pub fn vacuum_store (file_path: String) -> Result<(), ClassicJsError> {
+
+    //Locking the ls directory the database is in, the same lock write_data takes
+    let ls_path: PathBuf = PathBuf::from(&file_path).parent().map(PathBuf::from).unwrap_or_default();
+    let _lock: File = lock_store(&ls_path.to_string_lossy())?;
+
+    let conn: Connection = open_store(&file_path)?;
+
+    vacuum_conn(&conn)?;
+
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_micros() as u64;
+    conn.execute("UPDATE database SET last_vacuum_time = ?1, last_vacuum_size = ?2", (timestamp, database_size(&conn)?))?;
+
+    return Ok(());
+
+}
+
+/**
+ * Following function builds a localStorage database for the savedGame and
+ * settings json strings in memory, the same way write_data does, and returns
+ * the bytes of the resulting data.sqlite file without touching the disk
+ */
+pub fn build_sqlite_bytes (json_strings: [String; 2], website: Origin) -> Result<Vec<u8>, ClassicJsError> {
+
+    validate_saved_game(&json_strings[0], Some(MAX_CHANGED_BLOCKS))?;
+
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_micros() as u64;
+
+    let conn: Connection = Connection::open_in_memory()?;
+
+    fill_store(&conn, &json_strings, website.as_str(), &WriteOptions::default(), timestamp)?;
+
+    return Ok(conn.serialize(DatabaseName::Main)?.to_vec());
+
+}
+
+/**
+ * Following function opens an sqlite database at the provided path and
+ * changes the username stored in its settings, rewriting only the
+ * settings row and leaving the savedGame untouched. The settings are
+ * found the same way read_settings finds them, and written back under
+ * the key they were found under. If no settings are stored, default
+ * settings with the username are written under settings. A file that
+ * isn't a store is left untouched
+ */
+pub fn set_username (file_path: String, username: String) -> Result<(), ClassicJsError> {
+
+    let conn: Connection = open_store(&file_path)?;
+
+    //Locking the ls directory the database is in, the same lock write_data takes
+    let ls_path: PathBuf = PathBuf::from(&file_path).parent().map(PathBuf::from).unwrap_or_default();
+    let _lock: File = lock_store(&ls_path.to_string_lossy())?;
+
+    let (key, mut settings, compression): (String, Settings, Compression) = match query_settings(&conn, "settings")? {
+        Some(local) => {
+            let compression: Compression = match Compression::from_compression_type(local.compression_type) {
+                Some(compression) => compression,
+                None => return Err(ClassicJsError::UnsupportedCompression(local.compression_type))
+            };
+            (local.key.clone(), serde_json::from_str(&decode_local_storage(&local, &mut Codec::new())?)?, compression)
+        },
+        None => (String::from("settings"), Settings::default(), Compression::Snappy)
+    };
+
+    settings.username = username;
+    let settings_str: String = serialize_settings(settings);
+
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_micros() as i64;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO data (key, utf16_length, conversion_type, compression_type, last_access_time, value) values (?1, ?2, ?3, ?4, ?5, ?6)",
+        (key, settings_str.encode_utf16().count() as i32, 1, compression.compression_type(), timestamp, compress_value_with(settings_str.as_bytes(), compression, &mut Codec::new())?)
+    )?;
+
+    //Keeping the usage in the database table in step with the new length
+    conn.execute("UPDATE database SET usage = ?1", [stored_usage(&conn)?])?;
+
+    return Ok(());
+
+}
+
+/**
+ * Following function accepts a path to a db file, and a 
+ * json string. The json string is parsed as the value and
+ * compressed using snappy compression, and is then passed
+ * to the db and saved. Note this only applies to Firefox,
+ * as firefox is the only browser that I know of that uses
+ * this structure. Chromium support in the future...
+ * Any existing store for the website is overwritten, use
+ * write_data_with_options to refuse overwriting instead
+ * file_path is the folder holding the origin folders, which in a Firefox
+ * profile is its storage/default folder. The store is written to
+ * file_path/<origin folder>/ls/data.sqlite. To write into a profile
+ * given the profile folder itself, use write_data_to_profile
+ */
+pub fn write_data (file_path: String, json_strings: [String; 2], website: Origin) -> Result<(), ClassicJsError> {
+    let options: WriteOptions = WriteOptions { overwrite: true, ..WriteOptions::default() };
+    return write_data_with_options(file_path, json_strings, website, &options);
+}
+
+/**
+ * Following function returns the folder Firefox keeps the localStorage of
+ * websites in, inside a profile folder such as
+ * Profiles/########.default-release, which is profile_path/storage/default
+ */
+pub fn profile_storage_path (profile_path: &str) -> String {
+    return profile_path.trim_end_matches(['/', '\\']).to_string() + "/storage/default";
+}
+
+/**
+ * Same as write_data, but profile_path is the Firefox profile folder itself,
+ * e.g. Profiles/########.default-release. The store is written to
+ * profile_path/storage/default/<origin folder>/ls/data.sqlite, where
+ * Firefox looks for it. Firefox should be closed while writing, as it
+ * keeps the localStorage of sites it has open in memory
+ */
+pub fn write_data_to_profile (profile_path: String, json_strings: [String; 2], website: Origin) -> Result<(), ClassicJsError> {
+    return write_data(profile_storage_path(&profile_path), json_strings, website);
+}
+
+/**
+ * Following function checks a savedGame json string before it is written,
+ * rejecting world sizes the game can't load and saves with more changedBlocks
+ * than the given cap
+ */
+fn validate_saved_game (json_string: &str, max_changed_blocks: Option<usize>) -> Result<(), ClassicJsError> {
+    let level: JSLevel = serde_json::from_str(json_string)?;
+
+    if !WORLD_SIZES.contains(&level.worldSize) { return Err(ClassicJsError::InvalidWorldSize(level.worldSize)) }
+
+    if let Some(max) = max_changed_blocks {
+        if level.changedBlocks.len() > max { return Err(ClassicJsError::TooManyChangedBlocks(level.changedBlocks.len(), max)) }
+    }
+
+    return Ok(());
+}
+
+/**
+ * Same as write_data, but with the options for the write passed in.
+ * The savedGame is validated before anything is written to disk, and
+ * ClassicJsError::StoreExists is returned if the origin already has a
+ * data.sqlite and options.overwrite is false
+ */
+pub fn write_data_with_options (file_path: String, json_strings: [String; 2], website: Origin, options: &WriteOptions) -> Result<(), ClassicJsError> {
+
+    validate_saved_game(&json_strings[0], options.max_changed_blocks)?;
+
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_micros() as u64;
+
+    //Creating directories
+    let dir_name: String = origin_to_dir(website.as_str());
+
+    fs::create_dir_all(file_path.clone() + "/" + &dir_name + "/ls")?;
+
+    //Locking the store so concurrent writers can't interleave, released after the connection is dropped
+    let _lock: File = lock_store(&(file_path.clone() + "/" + &dir_name + "/ls"))?;
+
+    //Refusing to write into an existing store unless overwriting was asked for
+    let db_path: String = file_path.clone() + "/" + &dir_name + "/ls/data.sqlite";
+    match fs::metadata(&db_path) {
+        Ok(_) if !options.overwrite => return Err(ClassicJsError::StoreExists(db_path)),
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(ClassicJsError::Io(e)),
+        _ => ()
+    }
+
+    fs::write(file_path.clone() + "/" + &dir_name + "/.metadata-v2", build_metadata_v2(&website, timestamp))?;
+
+    let conn: Connection = Connection::open(&db_path)?;
+
+    fill_store(&conn, &json_strings, website.as_str(), options, timestamp)?;
+
+    fs::write(file_path.clone() + "/" + &dir_name + "/ls/usage", "")?;
+
+    Ok(())
+
+}
+
+
+
+/**
+ * Following function accepts a path to a db file, and a 
+ * json string. The json string is parsed as the value and
+ * compressed using snappy compression, and is then passed
+ * to the db and saved. Note this only applies to Firefox,
+ * as firefox is the only browser that I know of that uses
+ * this structure. Chromium support in the future...
+ */
+pub fn write_saved_game (file_path: String, json_string: String, website: Origin) -> Result<(), ClassicJsError> {
+
+    let settings: String = serialize_settings(Settings::default());
+    write_data(file_path, [json_string,settings], website)?;
+
+    return Ok(());
+
+}
+
+/**
+ * Following function writes a new world for the seed into the store of a
+ * website in base_path, along with the default settings, as write_saved_game
+ * does. A world straight from its seed has no changed blocks, as the game
+ * regenerates it from the seed, so nothing needs to be generated here.
+ * World sizes the game can't load are refused
+ */
+pub fn write_world_from_seed (base_path: String, website: Origin, seed: i64, world_size: i32) -> Result<(), ClassicJsError> {
+
+    let level: JSLevel = JSLevel::new(seed, HashMap::new(), world_size, 1);
+    write_saved_game(base_path, serialize_saved_game_sorted(&level), website)?;
+
+    return Ok(());
+
+}
+
+/**
+ * Following function looks through the storage/default folder of a Firefox
+ * profile and returns the origin of every website whose ls/data.sqlite has
+ * a savedGame stored, sorted. The origin is read from the .metadata-v2 of
+ * the folder, or worked back out of the folder name if that can't be read.
+ * Stores are opened the same way as by read_data, so gzipped ones are read
+ * with the gzip feature. Folders without a localStorage database, or with
+ * one this build can't open (gzipped without the gzip feature), are
+ * skipped, while other errors reading a store are returned
+ */
+pub fn list_game_origins (profile_storage_path: String) -> Result<Vec<String>, ClassicJsError> {
+
+    let mut origins: Vec<String> = Vec::new();
+
+    for entry in fs::read_dir(&profile_storage_path)? {
+        let dir: PathBuf = entry?.path();
+        let db_path: PathBuf = dir.join("ls/data.sqlite");
+        if !db_path.is_file() { continue }
+
+        //Opened like every other reader, so gzipped stores are listed too
+        let conn: Connection = match open_store(&db_path.to_string_lossy()) {
+            Ok(conn) => conn,
+            Err(ClassicJsError::NotAClassicStore(_) | ClassicJsError::Io(_) | ClassicJsError::UnsupportedFormat(_)) => continue,
+            Err(e) => return Err(e)
+        };
+        if query_local_storage(&conn, "savedGame")?.is_none() { continue }
+
+        let origin: String = match read_metadata_v2(dir.join(".metadata-v2").display().to_string()) {
+            Ok(metadata) => metadata.origin,
+            Err(_) => dir_to_origin(&dir.file_name().unwrap_or_default().to_string_lossy())
+        };
+        origins.push(origin);
+    }
+
+    origins.sort();
+    return Ok(origins);
+
+}
+
+/**
+ * Following function reverses origin_to_dir for an origin folder name,
+ * e.g. https+++classic.minecraft.net+8080 to https://classic.minecraft.net:8080
+ */
+fn dir_to_origin (dir_name: &str) -> String {
+    return match dir_name.split_once("+++") {
+        Some((scheme, rest)) => format!("{}://{}", scheme, rest.replace('+', ":")),
+        None => dir_name.replace('+', ":")
+    };
+}
+
+/**
+ * Following function deletes the storage folder of a website from the
+ * folder write_data wrote it into, removing the metadata, the ls folder
+ * and the database inside. Paths resolving outside of base_path are
+ * refused rather than deleted
+ */
+pub fn delete_origin (base_path: String, website: String) -> Result<(), ClassicJsError> {
+
+    let base: PathBuf = fs::canonicalize(&base_path)?;
+    let target: PathBuf = fs::canonicalize(base_path + "/" + &origin_to_dir(&website))?;
+
+    if target == base || !target.starts_with(&base) { return Err(ClassicJsError::OutsideBasePath(target.display().to_string())) }
+
+    fs::remove_dir_all(target)?;
+
+    return Ok(());
+
+}
+
+/**
+ * Following function rebuilds the files and rows Firefox needs alongside
+ * an existing data.sqlite, for stores written by older versions of this
+ * crate or by other tools. The usage is recomputed from the stored values,
+ * the database table row is rewritten, and the .metadata-v2 and usage
+ * files are regenerated. The data table itself is left untouched
+ */
+pub fn repair_store (base_path: String, website: Origin) -> Result<(), ClassicJsError> {
+
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_micros() as u64;
+
+    let dir_name: String = origin_to_dir(website.as_str());
+    let db_path: String = base_path.clone() + "/" + &dir_name + "/ls/data.sqlite";
+
+    //Only repairing stores that exist, rather than creating an empty one
+    fs::metadata(&db_path)?;
+
+    let _lock: File = lock_store(&(base_path.clone() + "/" + &dir_name + "/ls"))?;
+
+    fs::write(base_path.clone() + "/" + &dir_name + "/.metadata-v2", build_metadata_v2(&website, timestamp))?;
+
+    let conn: Connection = Connection::open(&db_path)?;
+
+    let usage: i64 = stored_usage(&conn)?;
+    let vacuum_size = fs::metadata(&db_path)?.len();
+
+    write_database_table(&conn, website.as_str(), usage, timestamp, vacuum_size)?;
+
+    fs::write(base_path.clone() + "/" + &dir_name + "/ls/usage", "")?;
+
+    return Ok(());
+
+}
@@ -0,0 +1,85 @@
+/*!
+ * Detecting which browser wrote a localStorage store, so callers can
+ * read a path without knowing which reader it needs
+ */
+
+use rusqlite::{Connection, OpenFlags};
+
+use std::path::{Path, PathBuf};
+
+use crate::{open_store, read_from_conn, ClassicJsError};
+
+/**
+ * StorageFormat enum stores the localStorage layouts used by browsers
+ * FirefoxSqlite: ls/data.sqlite with a compressed data table
+ * ChromiumLevelDb: a leveldb directory (not readable yet)
+ * SafariWebKit: a .localstorage sqlite file with an ItemTable table
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageFormat {
+    FirefoxSqlite,
+    ChromiumLevelDb,
+    SafariWebKit
+}
+
+/**
+ * Following function opens an sqlite file read only, without creating it,
+ * and checks which localStorage table it contains
+ */
+fn detect_sqlite_schema (path: &Path) -> Option<StorageFormat> {
+    let conn: Connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).ok()?;
+    let has_table = |table: &str| conn.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type='table' AND name=?1;",
+        [table],
+        |row| row.get::<_, i64>(0)
+    ).map(|count| count > 0).unwrap_or(false);
+
+    if has_table("data") { return Some(StorageFormat::FirefoxSqlite) }
+    if has_table("ItemTable") { return Some(StorageFormat::SafariWebKit) }
+    return None;
+}
+
+/**
+ * Following function inspects a path and returns its best guess at
+ * which browser's localStorage format is stored there. A directory
+ * containing data.sqlite or ls/data.sqlite is Firefox, and a directory
+ * that is or contains a leveldb folder (or has a leveldb CURRENT file)
+ * is Chromium. A sqlite file is Firefox if it has a data table, or
+ * Safari if it has an ItemTable table. None is returned if nothing matches
+ */
+pub fn detect_format (path: &str) -> Option<StorageFormat> {
+    let path: &Path = Path::new(path);
+
+    if path.is_dir() {
+        if path.join("data.sqlite").is_file() || path.join("ls/data.sqlite").is_file() { return Some(StorageFormat::FirefoxSqlite) }
+        if path.ends_with("leveldb") || path.join("leveldb").is_dir() || path.join("CURRENT").is_file() { return Some(StorageFormat::ChromiumLevelDb) }
+        return None;
+    }
+
+    if path.is_file() { return detect_sqlite_schema(path) }
+
+    return None;
+}
+
+/**
+ * Following function detects the format of the store at the given path
+ * and reads the specified object from it with the matching reader.
+ * None is returned if the object is not stored there
+ */
+pub fn read_any (path: &str, object: &str) -> Result<Option<String>, ClassicJsError> {
+    return match detect_format(path) {
+        Some(StorageFormat::FirefoxSqlite) => {
+            //Resolving an origin or ls folder down to the sqlite file
+            let mut db_path: PathBuf = PathBuf::from(path);
+            if db_path.is_dir() {
+                db_path = if db_path.join("data.sqlite").is_file() { db_path.join("data.sqlite") } else { db_path.join("ls/data.sqlite") };
+            }
+            let conn: Connection = open_store(&db_path.to_string_lossy())?;
+            read_from_conn(&conn, object)
+        },
+        #[cfg(feature = "safari")]
+        Some(StorageFormat::SafariWebKit) => crate::read_from_safari(String::from(path), object),
+        Some(format) => Err(ClassicJsError::UnsupportedFormat(format!("{:?}", format))),
+        None => Err(ClassicJsError::UnsupportedFormat(String::from(path)))
+    };
+}
@@ -0,0 +1,163 @@
+/*!
+ * Importing Java Classic levels, the gzipped .mine and .dat files the
+ * Java client saves, into a JSLevel and tile map
+ */
+
+use flate2::read::GzDecoder;
+
+use std::io::Read;
+
+use crate::{level_from_edited_map, reorder_from_java, ClassicJsError, JSLevel, WORLD_SIZES};
+
+//Magic number starting .dat levels, followed by the format version
+const LEVEL_MAGIC: u32 = 0x271bb788;
+
+//Start of a serialized byte[] in a version 2 .dat level: TC_ARRAY, TC_CLASSDESC
+//and the class name [B
+const BYTE_ARRAY_DESC: [u8; 6] = [0x75, 0x72, 0x00, 0x02, b'[', b'B'];
+
+/**
+ * Following function reads a Java Classic level and returns it as a JSLevel
+ * and its tile map. The seed of a Java level is unknown, so the JSLevel uses
+ * the default seed, see import_java_classic_with_seed
+ */
+pub fn import_java_classic (bytes: &[u8]) -> Result<(JSLevel, Vec<u8>), ClassicJsError> {
+    return import_java_classic_with_seed(bytes, JSLevel::default().worldSeed);
+}
+
+/**
+ * Following function reads a Java Classic level and returns it as a JSLevel
+ * and its tile map. Every block differing from the natural generation of
+ * seed is stored as a changed block, as the browser regenerates the world
+ * from the seed before placing them.
+ *
+ * Levels from 0.0.x (.mine, the raw blocks of a 256 world) and .dat levels
+ * of format version 1 and 2 are read. The level must be square, 64 blocks
+ * high and one of the WORLD_SIZES. Java tile ids are turned into the ids
+ * of classic js, see java_to_js_tile
+ */
+pub fn import_java_classic_with_seed (bytes: &[u8], seed: i64) -> Result<(JSLevel, Vec<u8>), ClassicJsError> {
+
+    let mut data: Vec<u8> = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut data)?;
+
+    let (world_size, blocks): (i32, &[u8]) = match u32::from_be_bytes(take(&data, 0, 4)?.try_into().unwrap()) {
+        LEVEL_MAGIC => match take(&data, 4, 1)?[0] {
+            1 => read_version_1(&data)?,
+            2 => read_version_2(&data)?,
+            version => return Err(ClassicJsError::UnsupportedFormat(format!("Java level version {}", version)))
+        },
+        //Levels from before the .dat format are only the blocks of a 256 world
+        _ => (256, &data[..])
+    };
+    check_dimensions(world_size, 64, world_size, blocks.len())?;
+
+    let mut tile_map: Vec<u8> = reorder_from_java(blocks, world_size);
+    for tile in tile_map.iter_mut() {
+        *tile = match java_to_js_tile(*tile) {
+            Some(tile) => tile,
+            None => return Err(ClassicJsError::UnsupportedFormat(format!("Java tile {}", tile)))
+        };
+    }
+
+    let level: JSLevel = level_from_edited_map(seed, &tile_map)?;
+    return Ok((level, tile_map));
+
+}
+
+/**
+ * Following function turns a Java Classic tile id into the classic js id of
+ * the same tile, for the tiles world generation places. Java has flowing
+ * and still water and lava, which classic js only has one of each of, and
+ * bedrock, which becomes rock. None is returned for other tiles
+ */
+pub fn java_to_js_tile (tile: u8) -> Option<u8> {
+    return match tile {
+        0 => Some(0), //Air
+        1 | 7 => Some(2), //Stone and bedrock to rock
+        2 => Some(1), //Grass
+        3 => Some(3), //Dirt
+        8 | 9 => Some(7), //Water
+        10 | 11 => Some(17), //Lava
+        12 => Some(11), //Sand
+        13 => Some(12), //Gravel
+        17 => Some(13), //Log to tree trunk
+        18 => Some(14), //Leaves
+        _ => None
+    };
+}
+
+/**
+ * Following function reads the header of a version 1 .dat level: the name,
+ * creator, creation time, then the width, length and height as shorts,
+ * followed by the blocks
+ */
+fn read_version_1 (data: &[u8]) -> Result<(i32, &[u8]), ClassicJsError> {
+
+    let mut pos: usize = 5;
+    for _ in 0..2 {
+        //Name and creator, each a java modified UTF-8 string
+        let len: usize = u16::from_be_bytes(take(data, pos, 2)?.try_into().unwrap()) as usize;
+        pos += 2 + len;
+    }
+    pos += 8; //Creation time
+
+    let mut dimensions: [i32; 3] = [0; 3];
+    for dimension in dimensions.iter_mut() {
+        *dimension = i16::from_be_bytes(take(data, pos, 2)?.try_into().unwrap()) as i32;
+        pos += 2;
+    }
+    let [width, length, height] = dimensions;
+
+    let blocks: &[u8] = take(data, pos, data.len().saturating_sub(pos))?;
+    check_dimensions(width, height, length, blocks.len())?;
+
+    return Ok((width, blocks));
+
+}
+
+/**
+ * Following function finds the blocks of a version 2 .dat level, which is a
+ * serialized java Level object. The blocks are its first byte array, and the
+ * world size is worked out from their count
+ */
+fn read_version_2 (data: &[u8]) -> Result<(i32, &[u8]), ClassicJsError> {
+
+    let start: usize = match data.windows(BYTE_ARRAY_DESC.len()).position(|window| window == BYTE_ARRAY_DESC) {
+        Some(start) => start,
+        None => return Err(ClassicJsError::UnsupportedFormat(String::from("Java level without blocks")))
+    };
+
+    //Skipping the serialVersionUID, flags, field count, end of block data and null superclass
+    let pos: usize = start + BYTE_ARRAY_DESC.len() + 8 + 1 + 2 + 1 + 1;
+    let len: usize = u32::from_be_bytes(take(data, pos, 4)?.try_into().unwrap()) as usize;
+    let blocks: &[u8] = take(data, pos + 4, len)?;
+
+    let world_size: i32 = ((len/64) as f64).sqrt() as i32;
+    return Ok((world_size, blocks));
+
+}
+
+/**
+ * Following function checks a Java level has the shape of a classic js
+ * world, square, 64 blocks high and one of the WORLD_SIZES
+ */
+fn check_dimensions (width: i32, height: i32, length: i32, len: usize) -> Result<(), ClassicJsError> {
+    if width <= 0 || width != length || height != 64 || (width as usize) * (height as usize) * (length as usize) != len {
+        return Err(ClassicJsError::UnsupportedFormat(format!("Java level of {} x {} x {} with {} blocks", width, height, length, len)));
+    }
+    if !WORLD_SIZES.contains(&width) { return Err(ClassicJsError::InvalidWorldSize(width)) }
+
+    return Ok(());
+}
+
+/**
+ * Following function returns count bytes of data starting at pos, or an
+ * error if the level ends before them
+ */
+fn take (data: &[u8], pos: usize, count: usize) -> Result<&[u8], ClassicJsError> {
+    return match data.get(pos..pos + count) {
+        Some(bytes) => Ok(bytes),
+        None => Err(ClassicJsError::UnsupportedFormat(String::from("truncated Java level")))
+    };
+}
@@ -1,25 +1,328 @@
-mod random_level_worker;
-mod random;
 
-use fancy_regex::{Regex, SubCaptureMatches};
+/*!
+ * Everything touching sqlite is behind the `sqlite` feature, and the
+ * rest of the filesystem behind `native`. Both are on by default, along
+ * with `bundled`, which builds rusqlite with its own copy of SQLite. With
+ * default-features = false and only `sqlite`, the system SQLite is linked
+ * instead. With default-features = false alone the crate builds for
+ * wasm32-unknown-unknown, keeping the json (de)serializers, the
+ * localStorage command builders, compression and world generation.
+ * 
+ * Gated behind `sqlite`: every function reading or writing a Firefox
+ * store (read_*, write_data*, build_sqlite_bytes, set_username,
+ * delete_origin, repair_store, read_metadata_v2, list_game_origins),
+ * diagnose_store, will_firefox_load, detect_format and read_any
+ * 
+ * Gated behind `native`: the write_*_command_to_file functions and the
+ * read_*_file and write_*_file functions
+ * 
+ * The `java-export` feature adds import_java_classic, for bringing Java
+ * Classic levels into the browser game
+ */
 
-use rusqlite::{Connection, Result};
+#![allow(clippy::needless_return)]
+
+#[cfg(feature = "sqlite")]
+mod diagnose;
+mod error;
+#[cfg(feature = "sqlite")]
+mod firefox;
+#[cfg(feature = "sqlite")]
+mod format;
+#[cfg(feature = "java-export")]
+mod java;
+mod random_level_worker;
+mod random;
+#[cfg(feature = "safari")]
+mod safari;
+
+#[cfg(feature = "sqlite")]
+pub use diagnose::{diagnose_store, will_firefox_load, Diagnostic, Severity};
+pub use error::ClassicJsError;
+#[cfg(feature = "sqlite")]
+pub use firefox::*;
+#[cfg(feature = "sqlite")]
+pub use format::{detect_format, read_any, StorageFormat};
+#[cfg(feature = "java-export")]
+pub use java::{import_java_classic, import_java_classic_with_seed, java_to_js_tile};
+pub use random_level_worker::GenerationParams;
+#[cfg(feature = "safari")]
+pub use safari::read_from_safari;
+
+use fancy_regex::Regex;
 
 use serde::{Deserialize, Serialize};
-use serde_json;
 
-use snap;
 use snap::raw::{Decoder, Encoder};
 
-use core::time;
-use std::collections::HashMap;
-use std::fs::{self, create_dir, Metadata};
-use std::time::SystemTime;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+#[cfg(feature = "native")]
+use std::fs;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/**
+ * The world sizes (width and length) classic js can generate
+ */
+pub const WORLD_SIZES: [i32; 3] = [128, 256, 512];
+
+/**
+ * Following function returns the number of blocks in the tile map of a
+ * world of the given size, world_size * 64 * world_size, as every world
+ * is 64 blocks high. Negative sizes have no blocks
+ */
+pub fn tile_map_len (world_size: i32) -> usize {
+    return (world_size.max(0) as usize).pow(2) * 64;
+}
+
+/**
+ * Following function returns the tile map index of the block at x, y, z.
+ * Tilemaps are stored in X,Z,Y format, where [0] is X:0, Y:0, Z:0 & [1] is
+ * X:1, Y:0, Z:0, so the index is (y * world_size + z) * world_size + x.
+ * None is returned for coordinates outside of the world
+ */
+pub fn xyz_to_index (world_size: i32, x: i32, y: i32, z: i32) -> Option<usize> {
+    if !(0..world_size).contains(&x) || !(0..64).contains(&y) || !(0..world_size).contains(&z) { return None }
+
+    return Some(((y*world_size*world_size) + (z*world_size) + x) as usize);
+}
+
+/**
+ * Following function returns the x, y, z of the block at a tile map index,
+ * the reverse of xyz_to_index. None is returned for indexes past the end of
+ * the tile map of world_size
+ */
+pub fn index_to_xyz (world_size: i32, index: usize) -> Option<(i32, i32, i32)> {
+    if index >= tile_map_len(world_size) { return None }
+
+    let size: usize = world_size as usize;
+    let x: i32 = (index % size) as i32;
+    let z: i32 = ((index / size) % size) as i32;
+    let y: i32 = (index / (size * size)) as i32;
+
+    return Some((x, y, z));
+}
+
+/**
+ * Following function checks the X,Z,Y order of tile maps, that x changes
+ * fastest, then z, then y. A tiny world is built with a block marking a
+ * step along each axis, and each marker is looked up by index and by
+ * coordinates. Panics if the order is broken, so tests catch an axis being
+ * transposed, which would mirror every saved world
+ */
+pub fn assert_tile_map_ordering () {
+    let world_size: i32 = 4;
+    let mut tile_map: Vec<u8> = vec![0; tile_map_len(world_size)];
+
+    //[1] is X:1, the next row of x is Z:1, the next layer of x and z is Y:1
+    let markers: [((i32, i32, i32), usize, u8); 4] = [
+        ((1, 0, 0), 1, 1),
+        ((0, 0, 1), 4, 2),
+        ((0, 1, 0), 16, 3),
+        ((3, 63, 3), 1023, 4)
+    ];
+    for &(_, index, block) in markers.iter() {
+        tile_map[index] = block;
+    }
+
+    for &((x, y, z), index, block) in markers.iter() {
+        assert_eq!(xyz_to_index(world_size, x, y, z), Some(index), "x {} y {} z {} is not at index {}", x, y, z, index);
+        assert_eq!(index_to_xyz(world_size, index), Some((x, y, z)), "index {} is not x {} y {} z {}", index, x, y, z);
+        assert_eq!(block_at(&tile_map, world_size, x, y, z), Some(block), "block_at x {} y {} z {} misses its marker", x, y, z);
+    }
+
+    //Every index maps to coordinates and back to itself
+    for index in 0..tile_map.len() {
+        let (x, y, z) = index_to_xyz(world_size, index).unwrap();
+        assert_eq!(xyz_to_index(world_size, x, y, z), Some(index));
+    }
+    assert_eq!(index_to_xyz(world_size, tile_map.len()), None);
+    assert_eq!(xyz_to_index(world_size, world_size, 0, 0), None);
+}
+
+/**
+ * The largest seed the generator can tell apart from the others. The seed
+ * is stored as an i64 in JSLevel, but world generation folds it into a
+ * Lehmer random with modulus 2^31 - 1, so only 1 to 2^31 - 2 are distinct
+ */
+pub const MAX_SEED: i64 = 2147483646;
+
+/**
+ * Following function checks whether a seed is one the generator uses
+ * as is, that is within 1 to MAX_SEED. Any other seed still generates
+ * a world, but the same world as the seed normalize_seed folds it into
+ */
+pub fn is_valid_seed (seed: i64) -> bool {
+    return (1..=MAX_SEED).contains(&seed);
+}
+
+/**
+ * Following function folds a seed into the seed the generator actually
+ * uses, the same way the game's random does: the seed is taken modulo
+ * 2^31 - 1 (keeping the sign of the seed), and MAX_SEED is added if the
+ * result is 0 or negative. So 0 generates the same world as MAX_SEED,
+ * and -1 the same world as MAX_SEED - 1
+ */
+pub fn normalize_seed (seed: i64) -> i32 {
+    return random::Random::new(seed).rand as i32;
+}
+
+/**
+ * Default cap on the number of changedBlocks write_data will accept,
+ * this is every block of the largest world so no legitimate save exceeds it
+ */
+pub const MAX_CHANGED_BLOCKS: usize = 512 * 512 * 64;
+
+/**
+ * WriteOptions struct stores the optional settings for write_data_with_options
+ * max_changed_blocks: Saves with more changedBlocks than this are rejected, None for no cap
+ * overwrite: Whether an existing data.sqlite for the origin may be written into, false by default
+ * compression: How values are compressed in the database, snappy by default to match Firefox
+ * last_access_time: Microseconds since the unix epoch stored as the values' last access,
+ * None for the time of writing. Firefox evicts the least recently accessed origins first
+ * keys: The keys the savedGame and settings are stored under, savedGame and settings
+ * by default. The game itself only reads savedGame and settings, so other keys such as
+ * savedGame2 are slots for tools keeping several worlds in one store
+ * vacuum: Whether the database is VACUUMed on every write, true by default. Skipping it
+ * saves rebuilding large databases on frequent writes, but the page_size and auto_vacuum
+ * pragmas then only apply to new stores, existing stores keep theirs and are written into
+ * as they are. The last_vacuum_time and last_vacuum_size of the database table are kept
+ * from the last VACUUM rather than set to the time and size of the write, so they fall
+ * behind the store until vacuum_store runs one on demand. A new store written without a
+ * VACUUM counts as vacuumed at the time it was written
+ */
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    pub max_changed_blocks: Option<usize>,
+    pub overwrite: bool,
+    pub compression: Compression,
+    pub last_access_time: Option<i64>,
+    pub keys: [String; 2],
+    pub vacuum: bool
+}
+
+impl WriteOptions {
+    pub fn new (max_changed_blocks: Option<usize>, overwrite: bool, compression: Compression, last_access_time: Option<i64>, keys: [String; 2], vacuum: bool) -> Self {
+        WriteOptions { max_changed_blocks, overwrite, compression, last_access_time, keys, vacuum }
+    }
+}
+
+impl Default for WriteOptions {
+    fn default () -> Self {
+        WriteOptions {
+            max_changed_blocks: Some(MAX_CHANGED_BLOCKS),
+            overwrite: false,
+            compression: Compression::Snappy,
+            last_access_time: None,
+            keys: [String::from("savedGame"), String::from("settings")],
+            vacuum: true
+        }
+    }
+}
+
+/**
+ * Compression enum stores the ways a value can be compressed inside the
+ * data table, matching the compression_type column
+ * None: 0, the value is stored as is
+ * Snappy: 1, snappy raw compression, which is what Firefox uses
+ * Gzip: 2, gzip compression, behind the gzip feature. Firefox does not
+ * know this compression_type, it is for stores made by other tools
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    None,
+    #[default]
+    Snappy,
+    #[cfg(feature = "gzip")]
+    Gzip
+}
+
+impl Compression {
+    pub fn compression_type (&self) -> i32 {
+        return match self {
+            Compression::None => 0,
+            Compression::Snappy => 1,
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => 2
+        };
+    }
+
+    pub fn from_compression_type (compression_type: i32) -> Option<Self> {
+        return match compression_type {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Snappy),
+            #[cfg(feature = "gzip")]
+            2 => Some(Compression::Gzip),
+            _ => None
+        };
+    }
+}
+
+/**
+ * Optimization enum stores the opt values serialize_saved_game takes,
+ * deciding which blocks are written to changedBlocks
+ * All: 0, every block of the world is written, a 128 world alone is over
+ * a million changedBlocks and the game lags loading it. Nothing is lost,
+ * even blocks matching natural generation are pinned
+ * ChangedOrDiff: 1, blocks differing from natural generation are written,
+ * as well as blocks already in changedBlocks. Keeps every block the level
+ * had, so reading and writing a save doesn't drop any of its entries
+ * DiffOnly: 2, only blocks differing from natural generation are written,
+ * the smallest savedGame. Changed blocks set back to their natural block
+ * are dropped, which the game loads as the same world
+ * Auto: 3, counts the blocks differing from natural generation and writes
+ * with All when at least AUTO_ALL_RATIO of the world differs, such as a fully
+ * custom build where diffing gains nothing, and with DiffOnly otherwise
+ * As levels made from a seed start without changedBlocks, ChangedOrDiff
+ * and DiffOnly give the same savedGame for them
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Optimization {
+    All,
+    #[default]
+    ChangedOrDiff,
+    DiffOnly,
+    Auto
+}
+
+impl Optimization {
+    pub fn opt (&self) -> u8 {
+        return match self {
+            Optimization::All => 0,
+            Optimization::ChangedOrDiff => 1,
+            Optimization::DiffOnly => 2,
+            Optimization::Auto => 3
+        };
+    }
+
+    pub fn from_opt (opt: u8) -> Option<Self> {
+        return match opt {
+            0 => Some(Optimization::All),
+            1 => Some(Optimization::ChangedOrDiff),
+            2 => Some(Optimization::DiffOnly),
+            3 => Some(Optimization::Auto),
+            _ => None
+        };
+    }
+}
+
+/**
+ * Share of the blocks of a world that must differ from natural generation
+ * for Optimization::Auto to write every block, 90%
+ */
+pub const AUTO_ALL_RATIO: f64 = 0.9;
+
+/**
+ * The optimization the functions not taking an opt serialize with, such as
+ * serialize_data and generate_saved_game_from_seed
+ */
+pub const DEFAULT_OPTIMIZATION: Optimization = Optimization::ChangedOrDiff;
 
 /**
  * Data struct stores the savedGame and settings of the world
  */
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Data {
     pub js_level: JSLevel,
     pub settings: Settings
@@ -41,22 +344,244 @@ impl Data {
  * changedBlocks: This is an array of all changedBlocks in the world
  * worldSize: This is the width/length of the world, must be 128, 256, or 512
  * version: Yeah, I have no clue what this is, but it's seemingly always 1 so...
+ * 
+ * When deserializing, a missing changedBlocks is read as empty and a missing
+ * version is read as 1. changedBlocks is still always written out, even
+ * when empty, as the game expects the key to be there
  */
-#[derive(Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JSLevel {
     pub worldSeed: i64,
+    #[serde(default)]
     pub changedBlocks: HashMap<String,ChangedBlocks>,
     pub worldSize: i32,
-    pub version: u8
+    #[serde(default = "default_version")]
+    pub version: u8,
+    //The js format does not persist a spawn point, the game picks its own
+    //on load. It is kept here for exporters to formats that do store one
+    #[serde(skip)]
+    pub spawn: Option<(i32, i32, i32)>
+}
+
+//Version every classic js save seen so far has used
+fn default_version () -> u8 { 1 }
+
+/**
+ * Levels are compared by what the savedGame stores, leaving out spawn,
+ * so a level read back from its json equals the level it was written from
+ */
+impl PartialEq for JSLevel {
+    fn eq (&self, other: &Self) -> bool {
+        return self.worldSeed == other.worldSeed
+            && self.changedBlocks == other.changedBlocks
+            && self.worldSize == other.worldSize
+            && self.version == other.version;
+    }
 }
 
+#[allow(non_snake_case)]
 impl JSLevel {
     pub fn new (worldSeed: i64, changedBlocks: HashMap<String,ChangedBlocks>, worldSize: i32, version: u8) -> Self {
-        JSLevel { worldSeed, changedBlocks, worldSize, version } 
+        JSLevel { worldSeed, changedBlocks, worldSize, version, spawn: None } 
+    }
+
+    /**
+     * Following function returns a short report of the changed blocks of
+     * the level, one line per region of 64 x 16 x 64 blocks with changes,
+     * most changed region first, e.g.
+     * 128 blocks changed in region (0-64, 0-16, 0-64): mostly rock (2)
+     * Keys not in the px_y_z form are counted on their own line
+     */
+    pub fn change_summary (&self) -> String {
+
+        //Counting the blocks of each type changed in each region
+        let mut regions: BTreeMap<(i32, i32, i32), HashMap<u8, usize>> = BTreeMap::new();
+        let mut unparsed: usize = 0;
+        for (key, block) in self.changedBlocks.iter() {
+            match parse_block_key(key) {
+                Some((x, y, z)) => {
+                    let region = (x.div_euclid(SUMMARY_REGION.0), y.div_euclid(SUMMARY_REGION.1), z.div_euclid(SUMMARY_REGION.2));
+                    *regions.entry(region).or_default().entry(block.bt).or_default() += 1;
+                },
+                None => unparsed += 1
+            }
+        }
+
+        let mut counted: Vec<((i32, i32, i32), usize, u8)> = regions.iter().map(|(region, blocks)| {
+            //The most changed block type, the lowest id on a tie
+            let (bt, _) = blocks.iter().max_by_key(|(bt, count)| (**count, std::cmp::Reverse(**bt))).unwrap();
+            (*region, blocks.values().sum(), *bt)
+        }).collect();
+        counted.sort_by_key(|(region, count, _)| (std::cmp::Reverse(*count), *region));
+
+        let mut output: String = format!("{} changed blocks in a {} world\n", self.changedBlocks.len(), self.worldSize);
+        for ((x, y, z), count, bt) in counted {
+            let (w, h, d) = SUMMARY_REGION;
+            output += &format!(
+                "{} blocks changed in region ({}-{}, {}-{}, {}-{}): mostly {} ({})\n",
+                count, x*w, (x + 1)*w, y*h, (y + 1)*h, z*d, (z + 1)*d, tile_name(bt), bt
+            );
+        }
+        if unparsed > 0 { output += &format!("{} keys not in the px_y_z form\n", unparsed) }
+
+        return output;
+
+    }
+
+    /**
+     * Following function makes a best guess at the optimization the level
+     * was saved with, regenerating its natural tile map to compare against.
+     * A level with a changed block for every block of the world is All, one
+     * with any changed block matching natural generation (or with a of 0) is
+     * ChangedOrDiff, and any other level is DiffOnly. Saves with no changed
+     * blocks, or only real differences, can't be told apart from DiffOnly.
+     * Keys not in the px_y_z form or outside the world are ignored
+     */
+    pub fn detected_optimization (&self) -> Optimization {
+
+        if self.changedBlocks.is_empty() { return Optimization::DiffOnly }
+        if self.changedBlocks.len() >= tile_map_len(self.worldSize) { return Optimization::All }
+
+        let natural: Vec<u8> = get_tile_map(self.worldSize, self.worldSeed);
+        let in_world = |coord: i32, size: i32| (0..size).contains(&coord);
+
+        for (key, block) in self.changedBlocks.iter() {
+            if let Some((x, y, z)) = parse_block_key(key) {
+                if !in_world(x, self.worldSize) || !in_world(y, 64) || !in_world(z, self.worldSize) { continue }
+
+                let index: usize = ((y*self.worldSize*self.worldSize) + (z*self.worldSize) + x) as usize;
+                if block.a == 0 || natural[index] == block.bt { return Optimization::ChangedOrDiff }
+            }
+        }
+
+        return Optimization::DiffOnly;
+
+    }
+
+    /**
+     * Following function re-keys every changedBlock into the canonical
+     * px_y_z form the game writes, e.g. p007_+2_3 -> p7_2_3, and drops the
+     * keys not in that form or outside the world. When two keys name the same
+     * block, the one already canonical wins, otherwise the lowest key. Two
+     * equivalent levels normalize to the same changedBlocks, and
+     * serialize_saved_game_sorted then writes them in the same order
+     */
+    pub fn normalize (&mut self) {
+        let world_size: i32 = self.worldSize;
+        let in_world = |x: i32, y: i32, z: i32| (0..world_size).contains(&x) && (0..64).contains(&y) && (0..world_size).contains(&z);
+
+        let mut entries: Vec<(String, ChangedBlocks)> = self.changedBlocks.drain().collect();
+        entries.sort_by(|(key1, _), (key2, _)| key1.cmp(key2));
+
+        let mut normalized: HashMap<String, ChangedBlocks> = HashMap::with_capacity(entries.len());
+        let mut renamed: Vec<(String, ChangedBlocks)> = Vec::new();
+        for (key, block) in entries {
+            if let Some((x, y, z)) = parse_block_key(&key) {
+                if !in_world(x, y, z) { continue }
+
+                let canonical: String = format!("p{}_{}_{}", x, y, z);
+                if canonical == key { normalized.insert(canonical, block); } else { renamed.push((canonical, block)) }
+            }
+        }
+        for (canonical, block) in renamed {
+            normalized.entry(canonical).or_insert(block);
+        }
+
+        self.changedBlocks = normalized;
+    }
+}
+
+//Size of the regions change_summary groups changed blocks into
+const SUMMARY_REGION: (i32, i32, i32) = (64, 16, 64);
+
+/**
+ * Following function names the tiles world generation places, which are
+ * the ones most edits replace. Other tiles are named by their id only
+ */
+fn tile_name (bt: u8) -> &'static str {
+    return match bt {
+        0 => "air",
+        1 => "grass",
+        2 => "rock",
+        3 => "dirt",
+        7 => "water",
+        11 => "sand",
+        12 => "gravel",
+        13 => "tree trunk",
+        14 => "leaves",
+        17 => "lava",
+        _ => "tile"
+    };
+}
+
+impl Default for JSLevel {
+    fn default () -> Self {
+        JSLevel { worldSeed: 1, changedBlocks: HashMap::new(), worldSize: 256, version: 1, spawn: None }
+    }
+}
+
+/**
+ * LevelVersion enum stores the savedGame versions this crate knows,
+ * matching the version key of the savedGame
+ * V1: 1, the only version classic js has written so far
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LevelVersion {
+    #[default]
+    V1
+}
+
+impl LevelVersion {
+    pub fn version (&self) -> u8 {
+        return match self {
+            LevelVersion::V1 => 1
+        };
+    }
+
+    pub fn from_version (version: u8) -> Option<Self> {
+        return match version {
+            1 => Some(LevelVersion::V1),
+            _ => None
+        };
+    }
+}
+
+/**
+ * Following function migrates a JSLevel from its own version to the
+ * target version. A migration either returns a level fully in the target
+ * version, with its version key set to it, or an error leaving the
+ * caller's level as it was; levels are never partially migrated.
+ * Migrating to the version a level is already in returns it unchanged,
+ * and a level from a version this crate does not know, or a transition
+ * with no migration written for it, is an UnsupportedMigration error
+ */
+pub fn migrate_level (level: JSLevel, target: LevelVersion) -> Result<JSLevel, ClassicJsError> {
+
+    let source: LevelVersion = match LevelVersion::from_version(level.version) {
+        Some(source) => source,
+        None => return Err(ClassicJsError::UnsupportedMigration(level.version, target.version()))
+    };
+
+    return match (source, target) {
+        (LevelVersion::V1, LevelVersion::V1) => Ok(level)
+    };
+
+}
+
+impl TryFrom<&str> for JSLevel {
+    type Error = ClassicJsError;
+
+    fn try_from (json_string: &str) -> Result<Self, Self::Error> {
+        return Ok(serde_json::from_str(json_string)?);
     }
+}
 
-    pub fn default () -> Self {
-        JSLevel { worldSeed: 1, changedBlocks: HashMap::new(), worldSize: 256, version: 1 }
+impl FromStr for JSLevel {
+    type Err = ClassicJsError;
+
+    fn from_str (json_string: &str) -> Result<Self, Self::Err> {
+        return JSLevel::try_from(json_string);
     }
 }
 
@@ -69,15 +594,43 @@ impl JSLevel {
  * a: 0 if block does match natural generation / 1 if block does not match natural generation
  * bt: type of block
  */
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ChangedBlocks {pub a: u8, pub bt: u8}
 impl ChangedBlocks { pub fn new (a: u8, bt: u8) -> Self {ChangedBlocks { a, bt }}}
 
+/**
+ * Converts an (a, bt) tuple into ChangedBlocks
+ * 
+ * ```
+ * use mc_classic_js::ChangedBlocks;
+ * use std::collections::HashMap;
+ * 
+ * let changed_blocks: HashMap<String, ChangedBlocks> = HashMap::from([
+ *     (String::from("p1_30_2"), (1, 4).into()),
+ *     (String::from("p2_30_2"), (0, 1).into())
+ * ]);
+ * 
+ * assert_eq!(changed_blocks["p1_30_2"], ChangedBlocks::new(1, 4));
+ * assert_eq!(<(u8, u8)>::from(changed_blocks["p2_30_2"].clone()), (0, 1));
+ * ```
+ */
+impl From<(u8, u8)> for ChangedBlocks {
+    fn from ((a, bt): (u8, u8)) -> Self { ChangedBlocks::new(a, bt) }
+}
+
+/**
+ * Converts ChangedBlocks into an (a, bt) tuple
+ */
+impl From<ChangedBlocks> for (u8, u8) {
+    fn from (block: ChangedBlocks) -> Self { (block.a, block.bt) }
+}
+
 /**
  * Settings struct stores the json object containing all settings for javascript worlds
  * These settings include typical control and sound settings, but they also contain the username
  */
-#[derive(Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Settings {
     pub music: bool,
     pub sound: bool,
@@ -94,10 +647,16 @@ pub struct Settings {
     pub fog: String,
     pub saveLoc: String,
     pub loadLoc: String,
-    pub username: String
+    pub username: String,
+    //Keys this struct doesn't know, e.g. from a newer version of the game,
+    //kept so reading and writing back the settings doesn't drop them
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>
 }
 
+#[allow(non_snake_case)]
 impl Settings {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         music: bool,
         sound: bool,
@@ -116,10 +675,95 @@ impl Settings {
         loadLoc: String,
         username: String
     ) -> Self {
-        Settings { music, sound, invert, fps, drawDistance, forward, left, backward, right, jump, build, chat, fog, saveLoc, loadLoc, username }
+        Settings { music, sound, invert, fps, drawDistance, forward, left, backward, right, jump, build, chat, fog, saveLoc, loadLoc, username, extra: HashMap::new() }
+    }
+
+    /**
+     * Following function checks every key binding with is_valid_key_token,
+     * returning the (field, value) of each binding the game won't recognize,
+     * e.g. ("jump", "Spaec"). An empty vec means every binding is usable
+     */
+    pub fn validate_bindings (&self) -> Vec<(String, String)> {
+        let bindings: [(&str, &String); 10] = [
+            ("forward", &self.forward), ("left", &self.left), ("backward", &self.backward), ("right", &self.right),
+            ("jump", &self.jump), ("build", &self.build), ("chat", &self.chat), ("fog", &self.fog),
+            ("saveLoc", &self.saveLoc), ("loadLoc", &self.loadLoc)
+        ];
+
+        return bindings.iter()
+            .filter(|(_, value)| !is_valid_key_token(value))
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect();
+    }
+
+    /**
+     * Following function converts the settings into a map of field name to
+     * value as a string, e.g. "music" -> "false", for editing settings
+     * without knowing their fields. Unknown keys kept in extra are included,
+     * strings as they are and other values as json
+     */
+    pub fn to_map (&self) -> HashMap<String, String> {
+        let mut map: HashMap<String, String> = HashMap::new();
+        if let serde_json::Value::Object(object) = serde_json::to_value(self).unwrap() {
+            for (key, value) in object {
+                let value: String = match value {
+                    serde_json::Value::String(string) => string,
+                    value => value.to_string()
+                };
+                map.insert(key, value);
+            }
+        }
+
+        return map;
     }
 
-    pub fn default () -> Self {
+    /**
+     * Following function builds settings from a map made by to_map. The
+     * bool fields must be true or false and drawDistance a number, and every
+     * field must be in the map, otherwise a Json error is returned. Keys
+     * that aren't a field are kept in extra, parsed as json if they are
+     * valid json and as a string otherwise
+     */
+    pub fn from_map (map: &HashMap<String, String>) -> Result<Settings, ClassicJsError> {
+        let mut object: serde_json::Map<String, serde_json::Value> = serde_json::Map::new();
+        for (key, value) in map.iter() {
+            let value: serde_json::Value = match key.as_str() {
+                "music" | "sound" | "invert" | "fps" | "drawDistance" => serde_json::from_str(value)?,
+                "forward" | "left" | "backward" | "right" | "jump" | "build" | "chat" | "fog" | "saveLoc" | "loadLoc" | "username" => {
+                    serde_json::Value::String(value.clone())
+                },
+                _ => serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.clone()))
+            };
+            object.insert(key.clone(), value);
+        }
+
+        return Ok(serde_json::from_value(serde_json::Value::Object(object))?);
+    }
+}
+
+/**
+ * Special key tokens the game accepts for a key binding, besides a single
+ * letter or digit
+ */
+pub const SPECIAL_KEY_TOKENS: [&str; 11] = [
+    "<space>", "<enter>", "<shift>", "<ctrl>", "<alt>", "<tab>", "<backspace>", "<up>", "<down>", "<left>", "<right>"
+];
+
+/**
+ * Following function checks that a key binding in the settings is a token
+ * the game recognizes. That is either a single ASCII letter or digit, e.g.
+ * W or 1, or one of the SPECIAL_KEY_TOKENS, e.g. <space>
+ */
+pub fn is_valid_key_token (s: &str) -> bool {
+    let mut chars = s.chars();
+    return match (chars.next(), chars.next()) {
+        (Some(c), None) => c.is_ascii_alphanumeric(),
+        _ => SPECIAL_KEY_TOKENS.contains(&s)
+    };
+}
+
+impl Default for Settings {
+    fn default () -> Self {
         Settings {
             music: false,
             sound: true,
@@ -136,26 +780,73 @@ impl Settings {
             fog: String::from("F"),
             saveLoc: String::from("<enter>"),
             loadLoc: String::from("R"),
-            username: String::from("noname")
+            username: String::from("noname"),
+            extra: HashMap::new()
         }
     }
 }
 
+impl TryFrom<&str> for Settings {
+    type Error = ClassicJsError;
+
+    fn try_from (json_string: &str) -> Result<Self, Self::Error> {
+        return Ok(serde_json::from_str(json_string)?);
+    }
+}
+
+impl FromStr for Settings {
+    type Err = ClassicJsError;
+
+    fn from_str (json_string: &str) -> Result<Self, Self::Err> {
+        return Settings::try_from(json_string);
+    }
+}
+
+/**
+ * SerializeStats struct stores what serialize_saved_game_with_stats wrote
+ * changed_blocks: Number of changedBlocks in the save
+ * json_len: Length of the json string in bytes, which with the save being
+ * ascii is also its length in the utf16_length column
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerializeStats {
+    pub changed_blocks: usize,
+    pub json_len: usize
+}
+
 /**
  * LocalStorage struct stores input from localStorage db files
  * key: "savedGame"
- * utf16_length: Length of uncompressed value
- * conversion_type: 1
+ * utf16_length: Length of uncompressed value in UTF-16 code units
+ * conversion_type: 1 if the value is stored as UTF-8, 0 if stored as UTF-16LE
  * compression_type: 1
+ * last_access_time: Microseconds since the unix epoch the value was last accessed, or 0
  * value: The actual savedGame, so the actual world
  */
+#[derive(Debug, Clone)]
 pub struct LocalStorage {
-    key: String,
-    utf16_length: i32,
-    conversion_type: i32,
-    compression_type: i32,
-    last_access_time: i32,
-    value: Vec<u8>
+    pub key: String,
+    pub utf16_length: i32,
+    pub conversion_type: i32,
+    pub compression_type: i32,
+    pub last_access_time: i64,
+    pub value: Vec<u8>
+}
+
+/**
+ * LocalStorageEntry struct stores a decoded value read from a
+ * localStorage db file, along with the row's metadata
+ * value: The decompressed value, e.g. the savedGame json
+ * utf16_length: Length of uncompressed value in UTF-16 code units
+ * compression_type: 1 for snappy compression
+ * last_access_time: Microseconds since the unix epoch the value was last accessed, or 0
+ */
+#[derive(Debug, Clone)]
+pub struct LocalStorageEntry {
+    pub value: String,
+    pub utf16_length: i32,
+    pub compression_type: i32,
+    pub last_access_time: i64
 }
 
 /**
@@ -167,6 +858,30 @@ pub fn deserialize_saved_game (json_string: String) -> JSLevel {
     return level;
 }
 
+/**
+ * LevelHeader struct is the part of a savedGame read_level_header reads.
+ * changedBlocks isn't a field, so serde skips over it without building it
+ */
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct LevelHeader {
+    worldSeed: i64,
+    worldSize: i32,
+    #[serde(default = "default_version")]
+    version: u8
+}
+
+/**
+ * Following function reads only the worldSeed, worldSize and version of a
+ * savedGame json string, e.g. for listing many worlds. The changedBlocks are
+ * skipped over without being built, which for big saves is most of the cost
+ * of deserializing. A missing version is read as 1, the same as JSLevel
+ */
+pub fn read_level_header (json: &str) -> Result<(i64, i32, u8), ClassicJsError> {
+    let header: LevelHeader = serde_json::from_str(json)?;
+    return Ok((header.worldSeed, header.worldSize, header.version));
+}
+
 /**
  * Converts a json string in the settings format into
  * a Settings struct
@@ -183,29 +898,185 @@ pub fn deserialize_settings (json_string: String) -> Settings {
 pub fn deserialize_data (json_string1: String, json_string2: String) -> Data {
     let level: JSLevel = serde_json::from_str(&json_string1).unwrap();
     let settings: Settings = serde_json::from_str(&json_string2).unwrap();
-    return Data { js_level: level, settings: settings}
+    return Data { js_level: level, settings }
+}
+
+/**
+ * Following function reads a savedGame json file, e.g. a shared
+ * savedGame.json, into a JSLevel. An unreadable file gives an Io error,
+ * and a file that isn't a savedGame gives a Json error
+ */
+#[cfg(feature = "native")]
+pub fn read_saved_game_file (path: String) -> Result<JSLevel, ClassicJsError> {
+    let json_string: String = fs::read_to_string(path)?;
+    return Ok(serde_json::from_str(&json_string)?);
+}
+
+/**
+ * Following function reads a settings json file into a Settings struct.
+ * An unreadable file gives an Io error, and a file that isn't a settings
+ * object gives a Json error
+ */
+#[cfg(feature = "native")]
+pub fn read_settings_file (path: String) -> Result<Settings, ClassicJsError> {
+    let json_string: String = fs::read_to_string(path)?;
+    return Ok(serde_json::from_str(&json_string)?);
 }
 
 /**
  * Following function accepts a level in the JS form, a tile_map, and optimization and
- * writes it into the classic javascript object format
+ * writes it into the classic javascript object format. The tile map must hold
+ * exactly worldSize * 64 * worldSize blocks. The output is deterministic, as
+ * changedBlocks are written in y, z, x order of the tile map. opt is one of
+ * the values of Optimization, see it for what each keeps. The natural
+ * generation of the level's seed is kept for the next call, see
+ * clear_generation_cache
+ */
+pub fn serialize_saved_game (level: JSLevel, tile_map: Vec<u8>, opt: u8) -> Result<String, ClassicJsError> {
+    check_tile_map_len(&tile_map, level.worldSize)?;
+
+    let tile_map1: Arc<Vec<u8>> = natural_tile_map(level.worldSize, level.worldSeed);
+    return Ok(serialize_saved_game_against(&level, &tile_map, &tile_map1, opt, &HashSet::new()).0);
+}
+
+/**
+ * Same as serialize_saved_game, but compared against the given natural
+ * tile map of the level's seed, e.g. from get_tile_map, instead of
+ * generating it. Both tile maps must hold exactly a world of worldSize
+ */
+pub fn serialize_saved_game_with_natural (level: JSLevel, tile_map: Vec<u8>, natural: &[u8], opt: u8) -> Result<String, ClassicJsError> {
+    check_tile_map_len(&tile_map, level.worldSize)?;
+    check_tile_map_len(natural, level.worldSize)?;
+
+    return Ok(serialize_saved_game_against(&level, &tile_map, natural, opt, &HashSet::new()).0);
+}
+
+/**
+ * Same as serialize_saved_game, but the number of changedBlocks written
+ * and the length of the json are returned alongside it. With opt 0 every
+ * block is written, so tools can check the stats before storing the save
+ */
+pub fn serialize_saved_game_with_stats (level: JSLevel, tile_map: Vec<u8>, opt: u8) -> Result<(String, SerializeStats), ClassicJsError> {
+    check_tile_map_len(&tile_map, level.worldSize)?;
+
+    let tile_map1: Arc<Vec<u8>> = natural_tile_map(level.worldSize, level.worldSeed);
+    return Ok(serialize_saved_game_against(&level, &tile_map, &tile_map1, opt, &HashSet::new()));
+}
+
+/**
+ * Same as serialize_saved_game, but the natural generation of the level's
+ * seed is looked up in the cache instead of being regenerated every call
+ */
+pub fn serialize_saved_game_cached (level: JSLevel, tile_map: Vec<u8>, opt: u8, cache: &mut TileMapCache) -> Result<String, ClassicJsError> {
+    check_tile_map_len(&tile_map, level.worldSize)?;
+
+    let tile_map1: &[u8] = cache.get_or_generate(level.worldSize, level.worldSeed);
+    return Ok(serialize_saved_game_against(&level, &tile_map, tile_map1, opt, &HashSet::new()).0);
+}
+
+/**
+ * Same as serialize_saved_game, but the blocks at the (x, y, z) positions in
+ * force_include are always written, even when they match natural generation
+ * and opt would leave them out, e.g. every block an editor knows the user
+ * placed, so the edit history is kept while the rest is diffed. Positions
+ * outside of the world are ignored
+ */
+pub fn serialize_saved_game_with_forced (level: JSLevel, tile_map: Vec<u8>, opt: u8, force_include: &HashSet<(i32, i32, i32)>) -> Result<String, ClassicJsError> {
+    check_tile_map_len(&tile_map, level.worldSize)?;
+
+    let tile_map1: Arc<Vec<u8>> = natural_tile_map(level.worldSize, level.worldSeed);
+    return Ok(serialize_saved_game_against(&level, &tile_map, &tile_map1, opt, force_include).0);
+}
+
+/**
+ * Following function checks that a tile map holds exactly the
+ * world_size * 64 * world_size blocks of its world
+ */
+fn check_tile_map_len (tile_map: &[u8], world_size: i32) -> Result<(), ClassicJsError> {
+    let expected: usize = tile_map_len(world_size);
+
+    if tile_map.len() != expected { return Err(ClassicJsError::TileMapSizeMismatch(expected, tile_map.len())) }
+
+    return Ok(());
+}
+
+/**
+ * Following function works out the world size of a tile map from its
+ * length, for functions given only a tile map. The size must be one of
+ * WORLD_SIZES, and the tile map must hold exactly a world of that size
+ */
+fn world_size_of_tile_map (tile_map: &[u8]) -> Result<i32, ClassicJsError> {
+    let world_size: i32 = ((tile_map.len()/64) as f64).sqrt() as i32;
+
+    if !WORLD_SIZES.contains(&world_size) { return Err(ClassicJsError::InvalidWorldSize(world_size)) }
+    check_tile_map_len(tile_map, world_size)?;
+
+    return Ok(world_size);
+}
+
+/**
+ * Following function does the work of serialize_saved_game, comparing
+ * the tile map against the already generated natural tile map tile_map1.
+ * The blocks at the (x, y, z) positions in force_include are always written
  */
-pub fn serialize_saved_game (level: JSLevel, tile_map: Vec<u8>, opt: u8) -> String {
+fn serialize_saved_game_against (level: &JSLevel, tile_map: &[u8], tile_map1: &[u8], opt: u8, force_include: &HashSet<(i32, i32, i32)>) -> (String, SerializeStats) {
 
     //Assigning x, y, and z of world
     let x: i32 = level.worldSize;
     let y: i32 = 64;
     let z: i32 = level.worldSize;
-    let tile_map1 = get_tile_map(level.worldSize, level.worldSeed);
 
     let mut output: String = String::from("{"); //Opening json object
 
-    output += &format!(r#""worldSeed":{},"#,level.worldSeed.to_string()); //Adding seed key value pair
+    output += &format!(r#""worldSeed":{},"#,level.worldSeed); //Adding seed key value pair
 
     //Adding changed blocks key value pair
     output += r#""changedBlocks":"#; //Adding blocks key
     output += "{"; //Opening block values object
 
+    //Turning the changedBlocks into tile map indexes once, sorted so they can be walked
+    //alongside the tile map instead of formatting and looking up a key for every block.
+    //Only keys exactly in the px_y_z form serialize_saved_game writes are used, and
+    //a bt of 255 counts as no changed block
+    let mut stored: Vec<(usize, u8)> = Vec::new();
+    for (key, block) in level.changedBlocks.iter() {
+        if let Some((k, i, j)) = parse_block_key(key) {
+            if block.bt != 255 && (0..x).contains(&k) && (0..y).contains(&i) && (0..z).contains(&j) && *key == format!("p{}_{}_{}", k, i, j) {
+                stored.push((((i*z*x) + (j*x) + k) as usize, block.bt));
+            }
+        }
+    }
+    stored.sort_unstable();
+
+    //Resolving Auto to All or DiffOnly by how much of the world differs
+    let opt: u8 = match opt {
+        3 => {
+            let mut next: usize = 0;
+            let mut differing: usize = 0;
+            for (index, (bt, natural_bt)) in tile_map.iter().zip(tile_map1.iter()).enumerate() {
+                let mut bt: u8 = *bt;
+                if next < stored.len() && stored[next].0 == index {
+                    bt = stored[next].1;
+                    next += 1;
+                }
+                if bt != *natural_bt { differing += 1 }
+            }
+
+            if differing as f64 >= tile_map.len() as f64 * AUTO_ALL_RATIO { Optimization::All.opt() } else { Optimization::DiffOnly.opt() }
+        },
+        opt => opt
+    };
+
+    let mut next: usize = 0;
+
+    //Forced positions as sorted tile map indexes too, leaving out ones outside of the world
+    let mut forced: Vec<usize> = force_include.iter()
+        .filter(|(k, i, j)| (0..x).contains(k) && (0..y).contains(i) && (0..z).contains(j))
+        .map(|(k, i, j)| ((i*z*x) + (j*x) + k) as usize)
+        .collect();
+    forced.sort_unstable();
+    let mut next_forced: usize = 0;
+
     //Variables for the tiles and a value
     let mut t: u8;
     let mut t1: u8;
@@ -214,21 +1085,26 @@ pub fn serialize_saved_game (level: JSLevel, tile_map: Vec<u8>, opt: u8) -> Stri
     //Iterating through all blocks
     //Tilemaps are stored in X,Z,Y format, where [0] is X:0, Y:0, Z:0 & [1] is X:1, Y:0, Z:0 etc.
     let mut flag: bool = false;
+    let mut changed_blocks: usize = 0;
+    let mut index: usize = 0;
     for i in 0..y {
         for j in 0..z {
             for k in 0..x {
 
-                /* Following code block will be more useful once a changed blocks hashmap is implemented */
-
-                //Setting tile for changed block and checking whether it matches tile generated by seed
-                let mut flag1 = false;
-                let key: String = String::from(format!(r#"p{}_{}_{}"#,k,i,j));
                 //Grabbing the block directly from level
-                let bt: u8 = level.changedBlocks.get(&key).unwrap_or(&ChangedBlocks::new(1,255)).bt;
+                let mut bt: u8 = 255;
+                if next < stored.len() && stored[next].0 == index {
+                    bt = stored[next].1;
+                    next += 1;
+                }
+                let force: bool = next_forced < forced.len() && forced[next_forced] == index;
+                if force { next_forced += 1 }
                 //Grabbing block from passed in tile map
-                t = tile_map[((i*z*x) + (j*x) + k) as usize];
+                t = tile_map[index];
                 //Grabbing the block generated from world
-                t1 = tile_map1[((i*z*x) + (j*x) + k) as usize];
+                t1 = tile_map1[index];
+                index += 1;
+
                 if bt != 255 { t = bt }
                 if t == t1 { a = 0 } else { a = 1 } //a = 0 if changed block matches generation, a = 1 if changed block does not match generation
 
@@ -236,18 +1112,13 @@ pub fn serialize_saved_game (level: JSLevel, tile_map: Vec<u8>, opt: u8) -> Stri
                 //If opt == 1 either the tile differs from natural generation or it is already considered a changed block to write to array
                 //If opt == 0 tile is written to array
                 //Default value should be 1 or 2, opt 0 is storage intensive and causes unnecessary lag
-                if (opt == 2 && a == 1) || (opt == 1 && (bt != 255 || a == 1)) || opt == 0 { flag1 = true }
-                
-                if flag1 {
-                    //Creating key for changed block
-                    output += &key;
-
-                    //Creating value for changed block
-                    output += "{";
-                    output += &format!(r#""a":{},"bt":{}"#,a,t);
-                    output += "},";
+                //Forced blocks are written whatever the opt
+                if (opt == 2 && a == 1) || (opt == 1 && (bt != 255 || a == 1)) || opt == 0 || force {
+                    //Creating key and value for changed block
+                    let _ = write!(output, r#""p{}_{}_{}":{{"a":{},"bt":{}}},"#, k, i, j, a, t);
 
                     flag = true;
+                    changed_blocks += 1;
                 }
 
             }
@@ -261,254 +1132,1108 @@ pub fn serialize_saved_game (level: JSLevel, tile_map: Vec<u8>, opt: u8) -> Stri
     output += &format!{r#""version":{}"#,level.version}; //Adding version key value pair
 
     output += "}"; //Closing json object
-    return output;
+
+    let stats: SerializeStats = SerializeStats { changed_blocks, json_len: output.len() };
+    return (output, stats);
 
 }
 
 /**
- * Following function accepts a settings object and returns 
- * a serialized json string
+ * Following function checks whether the block at x, y, z of a tile map
+ * matches the block naturally generated there, the same comparison
+ * serialize_saved_game uses for the a flag of a changed block.
+ * Both tile maps are stored in X,Z,Y format. false is returned for
+ * coordinates outside of the world or past the end of either tile map
  */
-pub fn serialize_settings (settings: Settings) -> String {
-    let mut output: String = String::from("{"); //Opening json object
-    output += &format!{r#""music":{},"#,settings.music};
-    output += &format!{r#""sound":{},"#,settings.sound};
-    output += &format!{r#""invert":{},"#,settings.invert};
+pub fn is_natural_block (current: &[u8], natural: &[u8], x: i32, y: i32, z: i32, world_size: i32) -> bool {
+    return match (block_at(current, world_size, x, y, z), block_at(natural, world_size, x, y, z)) {
+        (Some(bt), Some(natural_bt)) => bt == natural_bt,
+        _ => false
+    };
+}
+
+/**
+ * ChangedPosition struct stores the position of a block in a world
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChangedPosition {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32
+}
+
+impl ChangedPosition {
+    pub fn new (x: i32, y: i32, z: i32) -> Self {
+        ChangedPosition { x, y, z }
+    }
+}
+
+/**
+ * ChangeKind enum stores how a block differs from natural generation
+ * Placed: The block was air and now is not
+ * Removed: The block was not air and now is
+ * Swapped: The block was not air, and now is a different block
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Placed,
+    Removed,
+    Swapped
+}
+
+/**
+ * Following function compares a tile map against its natural generation
+ * and returns every block differing from it, in y, z, x order, along with
+ * whether it was placed, removed or swapped for another block. This tells
+ * apart what the a flag of a changed block only marks as differing.
+ * An error is returned if either tile map is not worldSize * 64 *
+ * worldSize blocks
+ */
+pub fn classify_changes (current: &[u8], natural: &[u8], world_size: i32) -> Result<Vec<(ChangedPosition, ChangeKind)>, ClassicJsError> {
+    check_tile_map_len(current, world_size)?;
+    check_tile_map_len(natural, world_size)?;
+
+    let mut changes: Vec<(ChangedPosition, ChangeKind)> = Vec::new();
+    for (i, (bt, natural_bt)) in current.iter().zip(natural.iter()).enumerate() {
+        if bt == natural_bt { continue }
+
+        let kind: ChangeKind = match (*natural_bt, *bt) {
+            (0, _) => ChangeKind::Placed,
+            (_, 0) => ChangeKind::Removed,
+            _ => ChangeKind::Swapped
+        };
+
+        //Tilemaps are stored in X,Z,Y format
+        let i: i32 = i as i32;
+        let position: ChangedPosition = ChangedPosition::new(i % world_size, i / (world_size * world_size), (i / world_size) % world_size);
+        changes.push((position, kind));
+    }
+
+    return Ok(changes);
+}
+
+/**
+ * CompareReport struct stores how the world generated for a seed differs
+ * from a reference tile map, see compare_to_reference
+ * differing: Number of blocks that differ, counting blocks the reference is missing
+ * len_mismatch: The expected and actual length of the reference when it isn't
+ * exactly a world of the world size, None otherwise
+ * first_mismatches: Position, generated block and reference block of the first
+ * differing blocks, in y, z, x order, at most COMPARE_REPORT_MISMATCHES of them
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompareReport {
+    pub differing: usize,
+    pub len_mismatch: Option<(usize, usize)>,
+    pub first_mismatches: Vec<(ChangedPosition, u8, u8)>
+}
+
+impl CompareReport {
+    /**
+     * Returns whether the generated world matches the reference exactly
+     */
+    pub fn matches (&self) -> bool {
+        return self.differing == 0 && self.len_mismatch.is_none();
+    }
+}
+
+//Number of differing blocks compare_to_reference lists in its report
+pub const COMPARE_REPORT_MISMATCHES: usize = 10;
+
+/**
+ * Following function generates the world for the seed and compares it
+ * block by block against a reference tile map, e.g. one the game itself
+ * generated for the same seed, to check this crate reproduces the game's
+ * worlds. The reference must be in the X,Z,Y order of get_tile_map
+ */
+pub fn compare_to_reference (world_size: i32, seed: i64, reference_tile_map: &[u8]) -> CompareReport {
+    let generated: Vec<u8> = get_tile_map(world_size, seed);
+
+    let len_mismatch: Option<(usize, usize)> = match generated.len() == reference_tile_map.len() {
+        true => None,
+        false => Some((generated.len(), reference_tile_map.len()))
+    };
+
+    //Blocks missing from a short reference count as differing
+    let mut differing: usize = generated.len().saturating_sub(reference_tile_map.len());
+    let mut first_mismatches: Vec<(ChangedPosition, u8, u8)> = Vec::new();
+    for (i, (bt, reference_bt)) in generated.iter().zip(reference_tile_map.iter()).enumerate() {
+        if bt == reference_bt { continue }
+
+        differing += 1;
+        if first_mismatches.len() < COMPARE_REPORT_MISMATCHES {
+            //Tilemaps are stored in X,Z,Y format
+            let i: i32 = i as i32;
+            let position: ChangedPosition = ChangedPosition::new(i % world_size, i / (world_size * world_size), (i / world_size) % world_size);
+            first_mismatches.push((position, *bt, *reference_bt));
+        }
+    }
+
+    return CompareReport { differing, len_mismatch, first_mismatches };
+}
+
+/**
+ * Following function returns the block at x, y, z of a tile map, which
+ * is stored in X,Z,Y order. None is returned for coordinates outside of
+ * the world, or past the end of a tile map too short for world_size,
+ * instead of panicking
+ */
+pub fn block_at (tile_map: &[u8], world_size: i32, x: i32, y: i32, z: i32) -> Option<u8> {
+    let index: usize = xyz_to_index(world_size, x, y, z)?;
+    return tile_map.get(index).copied();
+}
+
+/**
+ * Following function counts the blocks of a tile map differing from the
+ * natural generation of the seed, the blocks serialize_saved_game writes
+ * with opt 2, without building the changedBlocks or json. The natural tile
+ * map is generated for it, unless it is the world serialize_saved_game or
+ * get_tile_map_arc last generated, so callers counting many edits of one
+ * world can hold on to get_tile_map_arc and compare themselves. If the
+ * tile map isn't world_size * 64 * world_size long, the blocks it is short
+ * by, or has past the end of the world, count as changed, as they do for
+ * compare_to_reference
+ */
+pub fn count_changes (current: &[u8], world_size: i32, seed: i64) -> usize {
+    let natural: Arc<Vec<u8>> = natural_tile_map(world_size, seed);
+
+    let missing: usize = current.len().abs_diff(natural.len());
+    return missing + current.iter().zip(natural.iter()).filter(|(bt, natural_bt)| bt != natural_bt).count();
+}
+
+/**
+ * Following function rebuilds the changedBlocks of a save from a tile map,
+ * for saves whose changedBlocks are corrupt, e.g. keys that don't parse or
+ * bt values out of range. The old changedBlocks are not looked at, every
+ * entry is recomputed from the tile map against natural generation of the
+ * seed. As there are no old changed blocks, ChangedOrDiff gives the same
+ * map as DiffOnly, and Auto picks All or DiffOnly as serialize_saved_game
+ * does. An error is returned if the tile map is not worldSize * 64 *
+ * worldSize blocks, rather than rebuilding a save missing the blocks past
+ * its end
+ */
+pub fn rebuild_changed_blocks (tile_map: &[u8], seed: i64, world_size: i32, opt: Optimization) -> Result<HashMap<String, ChangedBlocks>, ClassicJsError> {
+    check_tile_map_len(tile_map, world_size)?;
+
+    let natural: Arc<Vec<u8>> = natural_tile_map(world_size, seed);
+
+    let opt: Optimization = match opt {
+        Optimization::Auto => {
+            let differing: usize = tile_map.iter().zip(natural.iter()).filter(|(bt, natural_bt)| bt != natural_bt).count();
+            if differing as f64 >= tile_map.len() as f64 * AUTO_ALL_RATIO { Optimization::All } else { Optimization::DiffOnly }
+        },
+        opt => opt
+    };
+
+    let mut changed_blocks: HashMap<String, ChangedBlocks> = HashMap::new();
+    for (index, (bt, natural_bt)) in tile_map.iter().zip(natural.iter()).enumerate() {
+        //a = 0 if the block matches generation, a = 1 if it does not
+        let a: u8 = if bt == natural_bt { 0 } else { 1 };
+        if a == 0 && opt != Optimization::All { continue }
+
+        if let Some((x, y, z)) = index_to_xyz(world_size, index) {
+            changed_blocks.insert(format!("p{}_{}_{}", x, y, z), ChangedBlocks::new(a, *bt));
+        }
+    }
+
+    return Ok(changed_blocks);
+}
+
+/**
+ * Following function returns the height of the first air block above
+ * the highest solid block of a column of a tile map, which is where
+ * a player standing on that column would be. 0 is returned for a column
+ * containing only air, and for columns outside of the world or past the
+ * end of the tile map
+ */
+pub fn surface_height (tile_map: &[u8], world_size: i32, x: i32, z: i32) -> i32 {
+    for y in (0..64).rev() {
+        if block_at(tile_map, world_size, x, y, z).is_some_and(|bt| bt != 0) { return y + 1 }
+    }
+
+    return 0;
+}
+
+/**
+ * Following function picks a spawn point for a tile map, on the surface
+ * at the center of the world
+ */
+pub fn spawn_point (tile_map: &[u8], world_size: i32) -> (i32, i32, i32) {
+    let center: i32 = world_size/2;
+
+    return (center, surface_height(tile_map, world_size, center, center), center);
+}
+
+//Tiles the sky light passes through, air and leaves, everything else casts a shadow
+const LIGHT_PASSING_TILES: [u8; 2] = [0, 14];
+
+/**
+ * Following function brings the grass of an edited tile map back in line
+ * with how the game treats it. Grass (1) with a shadow casting block above
+ * it turns to dirt (3), as the game does when grass is no longer lit, and
+ * dirt lit by the sky turns to grass if it is within the game's grass
+ * spreading reach of lit grass: 1 block sideways, and from 3 blocks below
+ * to 1 block above the grass. Spreading is done in one pass from the
+ * grass already there, while the game keeps spreading over time. Edits
+ * are left as they are unless this is called. A tile map that isn't
+ * world_size * 64 * world_size blocks is left untouched
+ */
+pub fn recompute_surface (tile_map: &mut [u8], world_size: i32) {
+    if tile_map.len() != tile_map_len(world_size) { return }
+
+    let index = |x: i32, y: i32, z: i32| ((y*world_size*world_size) + (z*world_size) + x) as usize;
+
+    //Height of the highest shadow casting block of each column, -1 if none
+    let mut lit_from: Vec<i32> = vec![-1; (world_size*world_size) as usize];
+    for z in 0..world_size {
+        for x in 0..world_size {
+            for y in (0..64).rev() {
+                if !LIGHT_PASSING_TILES.contains(&tile_map[index(x, y, z)]) {
+                    lit_from[(z*world_size + x) as usize] = y;
+                    break;
+                }
+            }
+        }
+    }
+    let is_lit = |x: i32, y: i32, z: i32| y >= lit_from[(z*world_size + x) as usize];
+
+    let original: Vec<u8> = tile_map.to_vec();
+    for y in 0..64 {
+        for z in 0..world_size {
+            for x in 0..world_size {
+                let i: usize = index(x, y, z);
+
+                if original[i] == 1 && !is_lit(x, y, z) { tile_map[i] = 3 }
+
+                if original[i] == 3 && is_lit(x, y, z) {
+                    //Grass spreads to dirt from 3 blocks below to 1 block above it
+                    let mut near_grass: bool = false;
+                    for yy in (y - 1).max(0)..=(y + 3).min(63) {
+                        for zz in (z - 1).max(0)..=(z + 1).min(world_size - 1) {
+                            for xx in (x - 1).max(0)..=(x + 1).min(world_size - 1) {
+                                if original[index(xx, yy, zz)] == 1 && is_lit(xx, yy, zz) { near_grass = true }
+                            }
+                        }
+                    }
+                    if near_grass { tile_map[i] = 1 }
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Following function returns the blocks of a tile map in the order the
+ * Java Classic client stores them, (y * length + z) * width + x. Tile maps
+ * are stored in X,Z,Y format with x changing fastest, which is that same
+ * index, so the blocks are copied as they are. Exporters should still go
+ * through this function, rather than relying on the two orders matching
+ */
+pub fn reorder_to_java (tile_map: &[u8], world_size: i32) -> Vec<u8> {
+    debug_assert_eq!(tile_map.len(), tile_map_len(world_size));
+
+    return tile_map.to_vec();
+}
+
+/**
+ * Following function returns the blocks of a Java Classic level, stored
+ * in (y * length + z) * width + x order, as a tile map. This is the
+ * reverse of reorder_to_java
+ */
+pub fn reorder_from_java (blocks: &[u8], world_size: i32) -> Vec<u8> {
+    debug_assert_eq!(blocks.len(), tile_map_len(world_size));
+
+    return blocks.to_vec();
+}
+
+/**
+ * Anchor enum stores which part of a world resize_world keeps in place
+ * Center: The centers of the old and new world line up
+ * Corner: Block 0, 0 of the old and new world line up
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    #[default]
+    Center,
+    Corner
+}
+
+/**
+ * Following function resizes a tile map to another world size, cropping
+ * it when to_size is smaller and padding it with air when to_size is
+ * larger. The anchor decides which part of the old world is kept, e.g.
+ * the central 256 region of a 512 world with Anchor::Center. An error is
+ * returned if either size is not one of WORLD_SIZES, or the tile map is
+ * not from_size * 64 * from_size blocks
+ */
+pub fn resize_world (tile_map: &[u8], from_size: i32, to_size: i32, anchor: Anchor) -> Result<Vec<u8>, ClassicJsError> {
+    for size in [from_size, to_size] {
+        if !WORLD_SIZES.contains(&size) { return Err(ClassicJsError::InvalidWorldSize(size)) }
+    }
+    check_tile_map_len(tile_map, from_size)?;
+
+    let offset: i32 = match anchor {
+        Anchor::Center => (from_size - to_size)/2,
+        Anchor::Corner => 0
+    };
+
+    let mut resized: Vec<u8> = vec![0; tile_map_len(to_size)];
+    for y in 0..64 {
+        for z in 0..to_size {
+            for x in 0..to_size {
+                let (xx, zz): (i32, i32) = (x + offset, z + offset);
+
+                if (0..from_size).contains(&xx) && (0..from_size).contains(&zz) {
+                    resized[((y*to_size*to_size) + (z*to_size) + x) as usize] = tile_map[((y*from_size*from_size) + (zz*from_size) + xx) as usize];
+                }
+            }
+        }
+    }
+
+    return Ok(resized);
+}
+
+/**
+ * Following function resizes the world of a level, given its current
+ * tile map, with resize_world. The changedBlocks are then diffed against
+ * the natural generation of the level's seed at the new size, as that is
+ * what the browser will generate for the resized level
+ */
+pub fn resize_level (level: JSLevel, tile_map: &[u8], to_size: i32, anchor: Anchor) -> Result<JSLevel, ClassicJsError> {
+    check_tile_map_len(tile_map, level.worldSize)?;
+
+    let resized: Vec<u8> = resize_world(tile_map, level.worldSize, to_size, anchor)?;
+    let mut output: JSLevel = generate_saved_game_from_seed(level.worldSeed, resized)?;
+    output.version = level.version;
+
+    return Ok(output);
+}
+
+/**
+ * LevelDiff struct stores the differences between the changedBlocks of
+ * two levels, see diff_levels. Each list is in y, z, x order
+ * only_in_a: Blocks changed in a but not in b
+ * only_in_b: Blocks changed in b but not in a
+ * differing: Blocks changed in both, with the bt of a and then the bt of b
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LevelDiff {
+    pub only_in_a: Vec<(ChangedPosition, ChangedBlocks)>,
+    pub only_in_b: Vec<(ChangedPosition, ChangedBlocks)>,
+    pub differing: Vec<(ChangedPosition, u8, u8)>
+}
+
+impl LevelDiff {
+    /**
+     * Returns whether the two levels have the same changed blocks
+     */
+    pub fn is_empty (&self) -> bool {
+        return self.only_in_a.is_empty() && self.only_in_b.is_empty() && self.differing.is_empty();
+    }
+}
+
+/**
+ * Following function compares the changedBlocks of two levels, e.g. two
+ * saves of the same world, and lists the blocks only one of them changed
+ * and the blocks both changed to a different bt. Keys are compared by the
+ * position they name, so p07_1_2 and p7_1_2 are the same block. Keys not
+ * in the px_y_z form are left out
+ */
+pub fn diff_levels (a: &JSLevel, b: &JSLevel) -> LevelDiff {
+    let positions = |level: &JSLevel| -> HashMap<ChangedPosition, ChangedBlocks> {
+        level.changedBlocks.iter()
+            .filter_map(|(key, block)| parse_block_key(key).map(|(x, y, z)| (ChangedPosition::new(x, y, z), block.clone())))
+            .collect()
+    };
+    let (a, b) = (positions(a), positions(b));
+
+    let mut diff: LevelDiff = LevelDiff::default();
+    for (position, block) in a.iter() {
+        match b.get(position) {
+            Some(other) if other.bt != block.bt => diff.differing.push((*position, block.bt, other.bt)),
+            Some(_) => (),
+            None => diff.only_in_a.push((*position, block.clone()))
+        }
+    }
+    for (position, block) in b.iter() {
+        if !a.contains_key(position) { diff.only_in_b.push((*position, block.clone())) }
+    }
+
+    let order = |position: &ChangedPosition| (position.y, position.z, position.x);
+    diff.only_in_a.sort_by_key(|(position, _)| order(position));
+    diff.only_in_b.sort_by_key(|(position, _)| order(position));
+    diff.differing.sort_by_key(|(position, _, _)| order(position));
+
+    return diff;
+}
+
+/**
+ * Following function places the changedBlocks of stamp into base, moved
+ * by offset, e.g. to paste a small build at a chosen spot of a bigger
+ * world. Blocks landing outside of base's world are skipped, and blocks
+ * already changed in base are replaced. Only the changedBlocks of stamp
+ * are placed, not the natural generation of its seed. Returns the number
+ * of blocks placed. Keys of stamp not in the px_y_z form are skipped
+ */
+pub fn stamp_level (base: &mut JSLevel, stamp: &JSLevel, offset: (i32, i32, i32)) -> usize {
+    let world_size: i32 = base.worldSize;
+    let mut placed: usize = 0;
+
+    for (key, block) in stamp.changedBlocks.iter() {
+        let (x, y, z) = match parse_block_key(key) {
+            Some((x, y, z)) => (x.saturating_add(offset.0), y.saturating_add(offset.1), z.saturating_add(offset.2)),
+            None => continue
+        };
+        if !(0..world_size).contains(&x) || !(0..64).contains(&y) || !(0..world_size).contains(&z) { continue }
+
+        base.changedBlocks.insert(format!("p{}_{}_{}", x, y, z), block.clone());
+        placed += 1;
+    }
+
+    return placed;
+}
+
+/**
+ * Following function parses a changedBlocks key in the form px_y_z
+ * into its x, y and z. None is returned for keys not in that form
+ */
+fn parse_block_key (key: &str) -> Option<(i32, i32, i32)> {
+    let mut coords = key.strip_prefix('p')?.split('_').map(|coord| coord.parse::<i32>());
+
+    let (x, y, z) = (coords.next()?.ok()?, coords.next()?.ok()?, coords.next()?.ok()?);
+    if coords.next().is_some() { return None }
+
+    return Some((x, y, z));
+}
+
+/**
+ * Following function regenerates the natural tile map of a level and
+ * drops every changedBlock whose block matches natural generation, which
+ * saves written with opt 0 or 1 are full of. Only the blocks that really
+ * differ are kept. Keys outside of the world or not in the px_y_z form
+ * are kept as they are
+ */
+pub fn compact_level (mut level: JSLevel) -> JSLevel {
+    let natural: Vec<u8> = get_tile_map(level.worldSize, level.worldSeed);
+    let world_size: i32 = level.worldSize;
+
+    level.changedBlocks.retain(|key, block| {
+        match parse_block_key(key) {
+            Some((x, y, z)) if (0..world_size).contains(&x) && (0..64).contains(&y) && (0..world_size).contains(&z) => {
+                natural[((y*world_size*world_size) + (z*world_size) + x) as usize] != block.bt
+            },
+            _ => true
+        }
+    });
+
+    return level;
+}
+
+/**
+ * Following function accepts a settings object and returns 
+ * a serialized json string. Strings are escaped, so usernames
+ * and key bindings holding quotes or backslashes stay valid json
+ */
+pub fn serialize_settings (settings: Settings) -> String {
+    let mut output: String = String::from("{"); //Opening json object
+    output += &format!{r#""music":{},"#,settings.music};
+    output += &format!{r#""sound":{},"#,settings.sound};
+    output += &format!{r#""invert":{},"#,settings.invert};
     output += &format!{r#""fps":{},"#,settings.fps};
     output += &format!{r#""drawDistance":{},"#,settings.drawDistance};
-    output += &format!{r#""forward":"{}","#,settings.forward};
-    output += &format!{r#""left":"{}","#,settings.left};
-    output += &format!{r#""backward":"{}","#,settings.backward};
-    output += &format!{r#""right":"{}","#,settings.right};
-    output += &format!{r#""jump":"{}","#,settings.jump};
-    output += &format!{r#""build":"{}","#,settings.build};
-    output += &format!{r#""chat":"{}","#,settings.chat};
-    output += &format!{r#""fog":"{}","#,settings.fog};
-    output += &format!{r#""saveLoc":"{}","#,settings.saveLoc};
-    output += &format!{r#""loadLoc":"{}","#,settings.loadLoc};
-    output += &format!{r#""username":"{}""#,settings.username};
+    output += &format!{r#""forward":{},"#,serde_json::Value::from(settings.forward.as_str())};
+    output += &format!{r#""left":{},"#,serde_json::Value::from(settings.left.as_str())};
+    output += &format!{r#""backward":{},"#,serde_json::Value::from(settings.backward.as_str())};
+    output += &format!{r#""right":{},"#,serde_json::Value::from(settings.right.as_str())};
+    output += &format!{r#""jump":{},"#,serde_json::Value::from(settings.jump.as_str())};
+    output += &format!{r#""build":{},"#,serde_json::Value::from(settings.build.as_str())};
+    output += &format!{r#""chat":{},"#,serde_json::Value::from(settings.chat.as_str())};
+    output += &format!{r#""fog":{},"#,serde_json::Value::from(settings.fog.as_str())};
+    output += &format!{r#""saveLoc":{},"#,serde_json::Value::from(settings.saveLoc.as_str())};
+    output += &format!{r#""loadLoc":{},"#,serde_json::Value::from(settings.loadLoc.as_str())};
+    output += &format!{r#""username":{}"#,serde_json::Value::from(settings.username.as_str())};
+
+    //Writing back unknown keys, sorted so the output is stable
+    let mut extra: Vec<(&String, &serde_json::Value)> = settings.extra.iter().collect();
+    extra.sort_by_key(|(key, _)| *key);
+    for (key, value) in extra {
+        output += &format!{",{}:{}",serde_json::Value::from(key.as_str()),value};
+    }
+
     output += "}"; //Closing json object
     return output;
 }
 
+/**
+ * Following function accepts a level and serializes it as is, without a
+ * tile map, with its changedBlocks in a stable order: y, then z, then x,
+ * the same order serialize_saved_game writes them in, followed by any keys
+ * not in the px_y_z form sorted as strings. Serializing through serde
+ * instead writes the changedBlocks in the arbitrary order of the HashMap,
+ * so equal levels can give different strings
+ */
+pub fn serialize_saved_game_sorted (level: &JSLevel) -> String {
+    let mut keys: Vec<&String> = level.changedBlocks.keys().collect();
+    keys.sort_by_key(|key| match parse_block_key(key) {
+        Some((x, y, z)) => (0, y, z, x, ""),
+        None => (1, 0, 0, 0, key.as_str())
+    });
+
+    let mut output: String = String::from("{"); //Opening json object
+
+    output += &format!(r#""worldSeed":{},"#,level.worldSeed); //Adding seed key value pair
+
+    //Adding changed blocks key value pair
+    output += r#""changedBlocks":{"#;
+    for (i, key) in keys.iter().enumerate() {
+        if i > 0 { output += "," }
+        let block: &ChangedBlocks = &level.changedBlocks[*key];
+        output += &format!(r#"{}:{{"a":{},"bt":{}}}"#, serde_json::Value::from(key.as_str()), block.a, block.bt);
+    }
+    output += "},"; //Closing Changed Blocks object
+
+    output += &format!{r#""worldSize":{},"#,level.worldSize}; //Adding world size key value pair
+    output += &format!{r#""version":{}"#,level.version}; //Adding version key value pair
+
+    output += "}"; //Closing json object
+    return output;
+}
+
+/**
+ * Following function accepts a level and returns it as an indented
+ * json string, for reading by hand when debugging. This is NOT meant
+ * to be fed back into the game, use serialize_saved_game for that
+ */
+pub fn serialize_saved_game_pretty (level: &JSLevel) -> String {
+    return serde_json::to_string_pretty(level).unwrap(); //Cannot fail, every map key is a string
+}
+
+/**
+ * Following function accepts a settings object and returns it as an
+ * indented json string, for reading by hand when debugging. This is NOT
+ * meant to be fed back into the game, use serialize_settings for that
+ */
+pub fn serialize_settings_pretty (settings: &Settings) -> String {
+    return serde_json::to_string_pretty(settings).unwrap(); //Cannot fail, every map key is a string
+}
+
+/**
+ * Following function writes a level into a standalone savedGame json
+ * file, for sharing or version control. The changedBlocks are written in
+ * the stable order of serialize_saved_game_sorted, so saving an unchanged
+ * level gives the same file. read_saved_game_file reads it back
+ */
+#[cfg(feature = "native")]
+pub fn write_saved_game_file (path: String, level: &JSLevel) -> Result<(), ClassicJsError> {
+    fs::write(path, serialize_saved_game_sorted(level))?;
+    return Ok(());
+}
+
+/**
+ * Following function writes settings into a standalone settings json
+ * file, which read_settings_file reads back
+ */
+#[cfg(feature = "native")]
+pub fn write_settings_file (path: String, settings: &Settings) -> Result<(), ClassicJsError> {
+    fs::write(path, serialize_settings(settings.clone()))?;
+    return Ok(());
+}
+
 /**
  * Follwing function accepts a Data struct and returns two serialized json
  * strings
  */
 pub fn serialize_data (data: Data) -> [String; 2] {
     let tile_map = get_tile_map(data.js_level.worldSize, data.js_level.worldSeed);
-    let level_str: String = serialize_saved_game(data.js_level, tile_map, 1);
+    let level_str: String = serialize_saved_game_against(&data.js_level, &tile_map, &tile_map, DEFAULT_OPTIMIZATION.opt(), &HashSet::new()).0;
     let settings_str: String = serialize_settings(data.settings);
     return [level_str, settings_str]
 }
 
 /**
- * Following function opens an sqlite database at the provided path,
- * then retreives the specified object, and then decompresses it 
- * before returning it
+ * Following function computes the usage Firefox records for an origin's
+ * localStorage, in the usage column of the database table and in the
+ * ls/usage file, from its key value pairs. Firefox counts every item as
+ * the length of its key plus the length of its value, both in UTF-16
+ * code units, with no other overhead. This is what counts against the
+ * origin's quota, rather than the compressed size on disk
  */
-pub fn read_from_db (file_path: String, object: &str) -> Result<String> {
+pub fn compute_usage (entries: &[(&str, &str)]) -> i64 {
+    let mut usage: i64 = 0;
+    for (key, value) in entries {
+        usage += (key.encode_utf16().count() + value.encode_utf16().count()) as i64;
+    }
 
-    let conn: Connection = Connection::open(file_path)?;
+    return usage;
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT * FROM data where key=?1;"
-    )?;
+/**
+ * Following function estimates how many bytes the savedGame and settings
+ * json strings take up in localStorage, which is what counts against the
+ * origin's localStorage quota rather than the compressed size on disk.
+ * Keys and values are held as UTF-16, two bytes per code unit, see
+ * compute_usage. Firefox's default quota is set by dom.storage.default_quota,
+ * and an import going over it fails in the browser without any error shown
+ */
+pub fn estimate_storage_bytes (json_strings: &[String; 2]) -> usize {
+    let usage: i64 = compute_usage(&[("savedGame", &json_strings[0]), ("settings", &json_strings[1])]);
 
-    //Iterating through the database
-    let entries = stmt.query_map([object], |row| Ok(
-        LocalStorage {
-            key: row.get(0)?,
-            utf16_length: row.get(1)?,
-            conversion_type: row.get(2)?,
-            compression_type: row.get(3)?,
-            last_access_time: row.get(4)?,
-            value: row.get(5)?,
-        }
-    ))?;
-
-    //Retreiving the compressed save game object and length
-    let mut compressed_object: Vec<u8> = Vec::new();
-    let mut decompressed_length: i32 = 0;
-    for entry in entries {
-        let local: LocalStorage = entry.unwrap();
-        if local.key == object {
-            compressed_object = local.value;
-            decompressed_length = local.utf16_length;
-            break;
-        }
-    }
+    return usage as usize * 2;
+}
 
-    //Creating an array with the correct length for storing the decompressed bytes
-    let mut decompressed: Vec<u8> = Vec::new();
-    for _ in 0..decompressed_length {
-        decompressed.push(0);
-    }
+/**
+ * Codec struct holds a snappy Encoder and Decoder, so reading or writing
+ * many values in a loop reuses them instead of making new ones for every
+ * value. It is passed to compress_value_with and decompress_value_with
+ */
+pub struct Codec {
+    encoder: Encoder,
+    decoder: Decoder
+}
 
-    //Decompressing using snappy compression
-    Decoder::decompress(&mut Decoder::new(), &compressed_object, &mut decompressed).unwrap();
+impl Codec {
+    pub fn new () -> Self {
+        Codec { encoder: Encoder::new(), decoder: Decoder::new() }
+    }
+}
 
-    //Converting the character codes to characters
-    let mut characters: Vec<char> = Vec::new();
-    for ch in decompressed {
-        characters.push(ch as char)
+impl Default for Codec {
+    fn default () -> Self {
+        Codec::new()
     }
+}
 
-    //Returning the characters as a string
-    Ok(characters.iter().collect())
+/**
+ * Following function compresses a value the way it will be stored
+ * in the data table for the given compression
+ */
+pub fn compress_value (decompressed: &[u8], compression: Compression) -> Result<Vec<u8>, ClassicJsError> {
+    return compress_value_with(decompressed, compression, &mut Codec::new());
+}
+
+/**
+ * Same as compress_value, but compressing with the encoder of the given
+ * codec, for writing many values
+ */
+pub fn compress_value_with (decompressed: &[u8], compression: Compression, codec: &mut Codec) -> Result<Vec<u8>, ClassicJsError> {
+
+    return match compression {
+        Compression::None => Ok(decompressed.to_vec()),
+        Compression::Snappy => {
+            //Creating the output array
+            let max_comp_length = snap::raw::max_compress_len(decompressed.len());
+            let mut compressed: Vec<u8> = vec![0; max_comp_length];
+
+            //Compressing and trimming the output array to the compressed length
+            let length: usize = codec.encoder.compress(decompressed, &mut compressed)?;
+            compressed.truncate(length);
+            Ok(compressed)
+        },
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            std::io::Write::write_all(&mut encoder, decompressed)?;
+            Ok(encoder.finish()?)
+        }
+    };
 
 }
 
 /**
- * Following function opens an sqlite database at the provided path,
- * then retreives the specified object, and then decompresses it 
- * before returning it
+ * Following function decompresses a value stored in the data table
+ * with the given compression. The decompressed length is read from
+ * the snappy header, as the utf16_length column Firefox stores counts
+ * UTF-16 code units rather than bytes. Values are decompressed according
+ * to the compression_type stored with them, see Compression
  */
-pub fn read_saved_game (file_path: String) -> Result<String> {
-    return read_from_db(file_path, "savedGame");
+pub fn decompress_value (compressed: &[u8], compression: Compression) -> Result<Vec<u8>, ClassicJsError> {
+    return decompress_value_with(compressed, compression, &mut Codec::new());
 }
 
 /**
- * Following function opens an sqlite database at the provided path,
- * then retreives the specified object, and then decompresses it 
- * before returning it
+ * Same as decompress_value, but decompressing with the decoder of the
+ * given codec, for reading many values
  */
-pub fn read_settings (file_path: String) -> Result<String> {
-    return read_from_db(file_path, "settings");
+pub fn decompress_value_with (compressed: &[u8], compression: Compression, codec: &mut Codec) -> Result<Vec<u8>, ClassicJsError> {
+
+    return match compression {
+        Compression::None => Ok(compressed.to_vec()),
+        Compression::Snappy => {
+            let mut decompressed: Vec<u8> = Vec::new();
+            snappy_decompress_into(compressed, &mut decompressed, 0, &mut codec.decoder)?;
+            Ok(decompressed)
+        },
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            let mut decompressed: Vec<u8> = Vec::new();
+            std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(compressed), &mut decompressed)?;
+            Ok(decompressed)
+        }
+    };
+
 }
 
 /**
- * Following function accepts a path to a db file, and a 
- * json string. The json string is parsed as the value and
- * compressed using snappy compression, and is then passed
- * to the db and saved. Note this only applies to Firefox,
- * as firefox is the only browser that I know of that uses
- * this structure. Chromium support in the future...
+ * Following function decompresses a snappy compressed value into dst,
+ * so a single buffer can be reused across many reads. dst is cleared at
+ * the start, and then holds only the decompressed bytes. expected_len is
+ * reserved up front, e.g. the largest value read so far, the actual length
+ * is still read from the snappy header
  */
-pub fn write_data (file_path: String, json_strings: [String; 2], website: String) -> Result<()> {
+pub fn decompress_value_into (src: &[u8], dst: &mut Vec<u8>, expected_len: usize) -> Result<(), ClassicJsError> {
+    return snappy_decompress_into(src, dst, expected_len, &mut Decoder::new());
+}
 
-    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_micros() as u64;
+/**
+ * Following function decompresses a snappy compressed value into dst
+ * with the given decoder, for decompress_value_into and decompress_value_with
+ */
+fn snappy_decompress_into (src: &[u8], dst: &mut Vec<u8>, expected_len: usize, decoder: &mut Decoder) -> Result<(), ClassicJsError> {
 
-    //Creating directories
-    let regex = Regex::new(r#"/|:|\*|\?|"|>|<|\||\\"#).unwrap();
-    let substitution = "+";
-    let dir_name = regex.replace_all(&website, substitution);
+    dst.clear();
 
-    create_dir(file_path.clone() + "/" + &dir_name);
-    create_dir(file_path.clone() + "/" + &dir_name + "/ls");
+    //Sizing the buffer to the decompressed length, reusing its allocation when it is large enough
+    let length: usize = snap::raw::decompress_len(src)?;
+    dst.reserve(length.max(expected_len));
+    dst.resize(length, 0);
 
-    //Building metadata file
-    let mut metadata: Vec<u8> = Vec::new();
-    metadata.extend_from_slice(&timestamp.to_be_bytes()); //Timestamp
-    metadata.push(0); //Persisted
-    metadata.extend_from_slice(&(0 as i32).to_be_bytes()); //Suffix
-    metadata.extend_from_slice(&(0 as i32).to_be_bytes()); //Group
+    //Decompressing using snappy compression
+    decoder.decompress(src, dst)?;
+    return Ok(());
 
-    //Origin
-    metadata.extend_from_slice(&(website.len() as u16).to_be_bytes());
-    metadata.extend_from_slice(website.as_bytes());
-    //let chars: Vec<char> = website.chars().collect();
-    //for ch in chars {metadata.push(ch as u8)}
+}
 
-    metadata.push(0); //Is App
+/**
+ * Following function run-length encodes a tile map, or any other bytes,
+ * as (count, byte) pairs with counts of 1 to 255. Terrain is long runs of
+ * the same block, so this shrinks a tile map a lot, e.g. before sending or
+ * compressing it. rle_decode reverses it
+ */
+pub fn rle_encode (tile_map: &[u8]) -> Vec<u8> {
+    let mut encoded: Vec<u8> = Vec::new();
 
-    fs::write(file_path.clone() + "/" + &dir_name + "/.metadata-v2", metadata);
+    let mut blocks = tile_map.iter().peekable();
+    while let Some(bt) = blocks.next() {
+        let mut count: u8 = 1;
+        while count < u8::MAX && blocks.next_if_eq(&bt).is_some() { count += 1 }
 
-    let keys: Vec<&str> = vec!["savedGame", "settings"];
+        encoded.push(count);
+        encoded.push(*bt);
+    }
 
-    let conn: Connection = Connection::open(file_path.clone() + "/" + &dir_name + "/ls/data.sqlite")?;
+    return encoded;
+}
 
-    conn.pragma_update(None, "user_version", 80);
-    conn.pragma_update(None, "auto_vacuum", 2);
-    conn.pragma_update(None, "page_size", 1024);
+/**
+ * Following function decodes bytes made by rle_encode. An InvalidRle error
+ * is returned for an odd number of bytes or a count of 0, and a
+ * TileMapSizeMismatch error if the decoded length isn't expected_len
+ */
+pub fn rle_decode (bytes: &[u8], expected_len: usize) -> Result<Vec<u8>, ClassicJsError> {
+    if !bytes.len().is_multiple_of(2) { return Err(ClassicJsError::InvalidRle(String::from("odd number of bytes"))) }
+
+    //Checking the runs add up to expected_len before allocating anything
+    let mut len: usize = 0;
+    for pair in bytes.chunks_exact(2) {
+        if pair[0] == 0 { return Err(ClassicJsError::InvalidRle(String::from("run of 0 bytes"))) }
+        len += pair[0] as usize;
+    }
+    if len != expected_len { return Err(ClassicJsError::TileMapSizeMismatch(expected_len, len)) }
 
-    conn.execute("VACUUM", []);
+    let mut decoded: Vec<u8> = Vec::with_capacity(expected_len);
+    for pair in bytes.chunks_exact(2) {
+        decoded.resize(decoded.len() + pair[0] as usize, pair[1]);
+    }
 
-    //Creates the localStorage data table inside the database if it does not exist
-    conn.execute(
-        "CREATE TABLE if not exists data ( 
-        key TEXT PRIMARY KEY, 
-        utf16_length INTEGER NOT NULL, 
-        conversion_type INTEGER NOT NULL, 
-        compression_type INTEGER NOT NULL, 
-        last_access_time INTEGER NOT NULL DEFAULT 0, 
-        value BLOB NOT NULL)", 
-        []
-    )?;
+    return Ok(decoded);
+}
 
-    let mut len = 0;
+/**
+ * Origin struct stores a website origin in the scheme://host[:port] form
+ * Firefox files localStorage under, e.g. https://classic.minecraft.net.
+ * It can only be made through Origin::parse, so the origin written into a
+ * store always matches the one Firefox looks up for the site
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Origin(String);
+
+impl Origin {
+    /**
+     * Parses an origin, lowercasing the scheme and host and dropping a
+     * trailing slash and default port. The scheme must be http or https, the host must be
+     * a domain name, ip address or bracketed ipv6 address, and the port if
+     * given a number. Anything after the host and port other than a lone
+     * slash, such as a path or query, is an InvalidOrigin error
+     */
+    pub fn parse (website: &str) -> Result<Origin, ClassicJsError> {
+        let invalid = || ClassicJsError::InvalidOrigin(String::from(website));
+
+        let (scheme, rest) = website.trim().split_once("://").ok_or_else(invalid)?;
+        let scheme: String = scheme.to_ascii_lowercase();
+        if scheme != "http" && scheme != "https" { return Err(invalid()) }
+
+        let rest: &str = rest.strip_suffix('/').unwrap_or(rest);
+        if rest.contains(['/', '?', '#', '@']) { return Err(invalid()) }
+
+        //Splitting off the port, after the closing bracket of an ipv6 host
+        let port_start: usize = rest.rfind(']').unwrap_or(0);
+        let (host, port) = match rest[port_start..].find(':') {
+            Some(i) => (&rest[..port_start + i], Some(&rest[port_start + i + 1..])),
+            None => (rest, None)
+        };
+
+        let host: String = host.to_ascii_lowercase();
+        let valid_host: bool = if host.starts_with('[') {
+            host.ends_with(']') && host[1..host.len() - 1].parse::<std::net::Ipv6Addr>().is_ok()
+        } else {
+            !host.is_empty() && host.split('.').all(|label| !label.is_empty() && label.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-'))
+        };
+        if !valid_host { return Err(invalid()) }
+
+        //Default ports are left out, as they are in the origins Firefox uses
+        return match port.map(|port| port.parse::<u16>()) {
+            Some(Ok(80)) if scheme == "http" => Ok(Origin(format!("{}://{}", scheme, host))),
+            Some(Ok(443)) if scheme == "https" => Ok(Origin(format!("{}://{}", scheme, host))),
+            Some(Ok(port)) => Ok(Origin(format!("{}://{}:{}", scheme, host, port))),
+            Some(Err(_)) => Err(invalid()),
+            None => Ok(Origin(format!("{}://{}", scheme, host)))
+        };
+    }
 
-    //Inserting the savedGame into the database
-    let mut stmt = conn.prepare("INSERT OR REPLACE INTO data (key, utf16_length, conversion_type, compression_type, value) values (?1, ?2, ?3, ?4, ?5)" )?;
+    pub fn as_str (&self) -> &str {
+        return &self.0;
+    }
 
-    for i in 0..json_strings.len() {
-        //Converting the json_string into an array of chars
-        //let characters: Vec<char> = json_strings[i].chars().collect();
-        let utf16_length: i32  = json_strings[i].len() as i32;
+    /**
+     * Returns the host of the origin, without the scheme or port
+     */
+    pub fn host (&self) -> &str {
+        let rest: &str = self.0.split_once("://").map(|(_, rest)| rest).unwrap_or(&self.0);
+        let port_start: usize = rest.rfind(']').unwrap_or(0);
+
+        return match rest[port_start..].find(':') {
+            Some(i) => &rest[..port_start + i],
+            None => rest
+        };
+    }
+}
 
-        len += utf16_length;
+impl std::fmt::Display for Origin {
+    fn fmt (&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{}", self.0);
+    }
+}
 
-        //Converting chars to u8
-        let mut decompressed: Vec<u8> = Vec::new();
-        decompressed.extend_from_slice(json_strings[i].as_bytes());
+impl TryFrom<&str> for Origin {
+    type Error = ClassicJsError;
 
-        //Creating the output array
-        let max_comp_length = snap::raw::max_compress_len(decompressed.len());
-        let mut compressed: Vec<u8> = Vec::new();
-        for _ in 0..max_comp_length {
-            compressed.push(0);
-        }
+    fn try_from (website: &str) -> Result<Self, Self::Error> {
+        return Origin::parse(website);
+    }
+}
 
-        //Compressing and cleaning the compressed value
-        Encoder::compress(&mut Encoder::new(), &decompressed, & mut compressed).unwrap();
-        let mut b: u8 = 0;
-        while b == 0 {
-            b = compressed.pop().unwrap();
-        }
-        compressed.push(b);
+impl FromStr for Origin {
+    type Err = ClassicJsError;
 
-        stmt.execute((keys[i], utf16_length, 1, 1, compressed))?;
+    fn from_str (website: &str) -> Result<Self, Self::Err> {
+        return Origin::parse(website);
     }
+}
 
-    len += 10;
-    let vacuum_size = fs::metadata(file_path.clone() + "/" + &dir_name + "/ls/data.sqlite").unwrap().len();
+/**
+ * Following function converts a website origin into the name Firefox
+ * gives its storage folder, replacing every character that can't be
+ * used in a file name with a +
+ * e.g. https://classic.minecraft.net -> https+++classic.minecraft.net
+ */
+pub fn origin_to_dir (website: &str) -> String {
+    let regex = Regex::new(r#"/|:|\*|\?|"|>|<|\||\\"#).unwrap();
+    let substitution = "+";
+    return regex.replace_all(website, substitution).into_owned();
+}
 
-    conn.execute(
-        "CREATE TABLE if not exists database ( 
-        origin TEXT NOT NULL, 
-        usage INTEGER NOT NULL DEFAULT 0, 
-        last_vacuum_time INTEGER NOT NULL DEFAULT 0, 
-        last_analyze_time INTEGER NOT NULL DEFAULT 0, 
-        last_vacuum_size INTEGER NOT NULL DEFAULT 0)",
-        [])?;
+/**
+ * Following function writes a localStorage command to the given file,
+ * unless the file is an empty string. Without the native feature there
+ * is no filesystem to write to, so the file is ignored
+ */
+fn write_command_file (file: &str, command: &str) {
+    #[cfg(feature = "native")]
+    if !file.is_empty() {fs::write(file, command).expect("Error when writing to file")}
+    #[cfg(not(feature = "native"))]
+    let _ = (file, command);
+}
 
-    stmt = conn.prepare("INSERT OR REPLACE INTO database (origin,usage,last_vacuum_time,last_analyze_time,last_vacuum_size) values (?1, ?2, ?3, ?4, ?5)" )?;
+/**
+ * Following function creates a localStorage.setItem() command for the key
+ * savedGame with saved_game_command and writes it to the given file
+ */
+#[cfg(feature = "native")]
+pub fn write_saved_game_command_to_file (file_path: String, json_string: &str) -> Result<String, ClassicJsError> {
+    let output: String = saved_game_command(json_string);
+    fs::write(file_path, &output)?;
 
-    stmt.execute((&website,len,timestamp,0,vacuum_size))?;
+    return Ok(output);
+}
 
-    fs::write(file_path.clone() + "/" + &dir_name + "/ls/usage", "");
+/**
+ * Following function creates a localStorage.setItem() command for the key
+ * settings with settings_command and writes it to the given file
+ */
+#[cfg(feature = "native")]
+pub fn write_settings_command_to_file (file_path: String, json_string: &str) -> Result<String, ClassicJsError> {
+    let output: String = settings_command(json_string);
+    fs::write(file_path, &output)?;
 
-    Ok(())
+    return Ok(output);
+}
 
+/**
+ * Following function creates the localStorage.setItem() commands for the keys
+ * savedGame and settings with local_storage_command and writes them to the
+ * given file
+ */
+#[cfg(feature = "native")]
+pub fn write_local_storage_command_to_file (file_path: String, json_strings: &[String; 2]) -> Result<String, ClassicJsError> {
+    let output: String = local_storage_command(json_strings);
+    fs::write(file_path, &output)?;
+
+    return Ok(output);
 }
 
+/**
+ * Following function creates a localStorage.setItem() command for the given
+ * key and json string
+ */
+fn set_item_command (key: &str, json_string: &str) -> String {
+    let open: String = format!{r#"localStorage.setItem("{key}", `"#}; //Opening command for localStorage
+    let close: String = String::from(r#"`)"#); //Closing command for localStorage
 
+    return format!{r"{open}{json_string}{close}"};
+}
 
 /**
- * Following function accepts a path to a db file, and a 
- * json string. The json string is parsed as the value and
- * compressed using snappy compression, and is then passed
- * to the db and saved. Note this only applies to Firefox,
- * as firefox is the only browser that I know of that uses
- * this structure. Chromium support in the future...
+ * Following function excepts a world save formatted as a json string. 
+ * It then creates a localStorage.setItem() command for the key savedGame,
+ * in order for it to be copy pasted into a browser console to insert
+ * the world save
  */
-pub fn write_saved_game (file_path: String, json_string: String, website: String) -> Result<()> {
+pub fn saved_game_command (json_string: &str) -> String {
+    return set_item_command("savedGame", json_string);
+}
 
-    let settings: String = serialize_settings(Settings::default());
-    write_data(file_path, [json_string,settings], website);
+/**
+ * Following function excepts settings formatted as a json string. 
+ * It then creates a localStorage.setItem() command for the key settings,
+ * in order for it to be copy pasted into a browser console to insert
+ * the settings
+ */
+pub fn settings_command (json_string: &str) -> String {
+    return set_item_command("settings", json_string);
+}
 
-    return Ok(());
+/**
+ * Following function excepts an array containing both a world save and 
+ * settings formatted as json string. It then creates a 
+ * localStorage.setItem() command for the key savedGame and settings, 
+ * in order for it to be copy pasted into a browser console to 
+ * insert the world save
+ */
+pub fn local_storage_command (json_strings: &[String; 2]) -> String {
+    let mut output: String = set_item_command("savedGame", &json_strings[0]);
+    output += ";";
+    output += &set_item_command("settings", &json_strings[1]);
+
+    return output;
+}
+
+/**
+ * Following function creates the localStorage.setItem() command for a new
+ * world from the seed, for trying a seed by pasting the command into a
+ * browser console. A world straight from its seed has no changed blocks,
+ * as the game regenerates it from the seed, so no world is generated here.
+ * If a file is given the command is also written to it, which needs the
+ * native feature. World sizes the game can't load are refused
+ */
+pub fn seed_to_command (seed: i64, world_size: i32, file: Option<String>) -> Result<String, ClassicJsError> {
+    if !WORLD_SIZES.contains(&world_size) { return Err(ClassicJsError::InvalidWorldSize(world_size)) }
+
+    let level: JSLevel = JSLevel::new(seed, HashMap::new(), world_size, 1);
+    let output: String = saved_game_command(&serialize_saved_game_sorted(&level));
+
+    if let Some(file) = file {
+        #[cfg(feature = "native")]
+        fs::write(file, &output)?;
+        #[cfg(not(feature = "native"))]
+        return Err(ClassicJsError::Io(std::io::Error::new(std::io::ErrorKind::Unsupported, format!("cannot write {} without the native feature", file))));
+    }
 
+    return Ok(output);
+}
+
+/**
+ * Following function splits one or more localStorage.setItem() commands,
+ * separated by ;, back into their key and value pairs, e.g. a command made
+ * by local_storage_command gives the savedGame and settings json strings.
+ * The key may be in double or single quotes, and the value must be in
+ * backticks. An escaped backtick \` in the value is unescaped, any other
+ * backslash is kept as it is, as the command functions write values unescaped
+ */
+pub fn parse_set_item_command (command: &str) -> Result<Vec<(String, String)>, ClassicJsError> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    let mut rest: &str = command;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ';');
+        if rest.is_empty() {break}
+
+        rest = expect_token(rest, "localStorage.setItem")?;
+        rest = expect_token(rest, "(")?.trim_start();
+
+        //Key is a plain string literal in either kind of quote
+        let quote: char = match rest.chars().next() {
+            Some(c @ ('"' | '\'')) => c,
+            _ => return Err(ClassicJsError::InvalidCommand(String::from("key is not a quoted string")))
+        };
+        let key_end: usize = rest[1..].find(quote)
+            .ok_or_else(|| ClassicJsError::InvalidCommand(String::from("key is missing its closing quote")))?;
+        let key: String = rest[1..key_end + 1].to_string();
+        rest = &rest[key_end + 2..];
+
+        rest = expect_token(rest, ",")?;
+        rest = expect_token(rest, "`")?;
+
+        //Value runs up to the first backtick that isn't escaped
+        let mut value: String = String::new();
+        let mut chars = rest.char_indices();
+        let value_end: usize = loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, '`')) => value.push('`'),
+                    Some((_, c)) => {value.push('\\'); value.push(c)},
+                    None => value.push('\\')
+                },
+                Some((i, '`')) => break i,
+                Some((_, c)) => value.push(c),
+                None => return Err(ClassicJsError::InvalidCommand(format!("value of {} is missing its closing backtick", key)))
+            }
+        };
+        rest = &rest[value_end + 1..];
+
+        rest = expect_token(rest, ")")?;
+        pairs.push((key, value));
+    }
+
+    return Ok(pairs);
+}
+
+/**
+ * Following function skips any whitespace at the start of a command and
+ * then the given token, for parse_set_item_command
+ */
+fn expect_token<'a> (command: &'a str, token: &str) -> Result<&'a str, ClassicJsError> {
+    return command.trim_start().strip_prefix(token)
+        .ok_or_else(|| ClassicJsError::InvalidCommand(format!("expected {}", token)));
 }
 
 /**
@@ -517,13 +2242,11 @@ pub fn write_saved_game (file_path: String, json_string: String, website: String
  * savedGame, in order for it to be copy pasted into a browser console to 
  * insert the world save
  */
+#[deprecated(since="0.3.0", note="please use `saved_game_command` or `write_saved_game_command_to_file` instead")]
 pub fn write_saved_game_command (file: String, json_string: String) -> String {
-    let open: String = String::from(r#"localStorage.setItem("savedGame", `"#); //Opening command for localStorage
-    let close: String = String::from(r#"`)"#); //Closing command for localStorage
+    let output: String = saved_game_command(&json_string);
 
-    let output: String = String::from(format!{r"{open}{json_string}{close}"});
-
-    if file != "" {fs::write(file, output.clone()).expect("Error when writing to file")} //Attempting to write localStorage command to file
+    write_command_file(&file, &output); //Attempting to write localStorage command to file
 
     return output;
 
@@ -535,13 +2258,11 @@ pub fn write_saved_game_command (file: String, json_string: String) -> String {
  * settings, in order for it to be copy pasted into a browser console to 
  * insert the world save
  */
+#[deprecated(since="0.3.0", note="please use `settings_command` or `write_settings_command_to_file` instead")]
 pub fn write_settings_command (file: String, json_string: String) -> String {
-    let open: String = String::from(r#"localStorage.setItem("settings", `"#); //Opening command for localStorage
-    let close: String = String::from(r#"`)"#); //Closing command for localStorage
+    let output: String = settings_command(&json_string);
 
-    let output: String = String::from(format!{r"{open}{json_string}{close}"});
-
-    if file != "" {fs::write(file, output.clone()).expect("Error when writing to file")} //Attempting to write localStorage command to file
+    write_command_file(&file, &output); //Attempting to write localStorage command to file
 
     return output;
 
@@ -554,48 +2275,322 @@ pub fn write_settings_command (file: String, json_string: String) -> String {
  * in order for it to be copy pasted into a browser console to 
  * insert the world save
  */
+#[deprecated(since="0.3.0", note="please use `local_storage_command` or `write_local_storage_command_to_file` instead")]
 pub fn write_local_storage_command (file: String, json_strings: [String; 2]) -> String {
-    let open: String = String::from(r#"localStorage.setItem("savedGame", `"#); //Opening command for localStorage
-    let close: String = String::from(r#"`)"#); //Closing command for localStorage
-    let mut string: String = json_strings[0].clone();
-    
-    let mut output: String = String::from(format!{r"{open}{string}{close}"});
-    output += ";";
-    
-    string = json_strings[1].clone();
-    output += &format!{r"{open}{string}{close}"};
+    let output: String = local_storage_command(&json_strings);
 
-    if file != "" {fs::write(file, output.clone()).expect("Error when writing to file")} //Attempting to write localStorage command to file
+    write_command_file(&file, &output); //Attempting to write localStorage command to file
 
     return output;
 
 }
 
 /**
- * Following function takes a seed and creates a JSLevel from this seed
+ * Following function takes a seed and creates a JSLevel from this seed.
+ * The tile map is compared against the game's own generation, as that is
+ * what the browser regenerates before placing the changed blocks. The
+ * world size is worked out from the length of the tile map, which must
+ * hold exactly a world of one of the WORLD_SIZES
  */
-pub fn generate_saved_game_from_seed (seed: i64, tile_map: Vec<u8>) -> JSLevel {
+pub fn generate_saved_game_from_seed (seed: i64, tile_map: Vec<u8>) -> Result<JSLevel, ClassicJsError> {
+    return generate_saved_game_from_seed_with_opt(seed, tile_map, DEFAULT_OPTIMIZATION.opt());
+}
 
-    let world_size: i32 = ((tile_map.len()/64) as f64).sqrt() as i32;
+/**
+ * Same as generate_saved_game_from_seed, but with the optimization of
+ * serialize_saved_game. opt 0 lists every block of the world as a changed
+ * block, for tools wanting the full tile map in the savedGame. As the level
+ * starts without changed blocks, opt 1 gives the same level as opt 2
+ */
+pub fn generate_saved_game_from_seed_with_opt (seed: i64, tile_map: Vec<u8>, opt: u8) -> Result<JSLevel, ClassicJsError> {
+
+    let world_size: i32 = world_size_of_tile_map(&tile_map)?;
     let changed_blocks: HashMap<String, ChangedBlocks> = HashMap::new();
     let level = JSLevel::new(seed, changed_blocks, world_size, 1);
 
-    return deserialize_saved_game(serialize_saved_game(level, tile_map, 2));
+    let spawn: (i32, i32, i32) = spawn_point(&tile_map, world_size);
+
+    let mut output: JSLevel = deserialize_saved_game(serialize_saved_game(level, tile_map, opt)?);
+    output.spawn = Some(spawn);
+
+    return Ok(output);
 
 }
 
+/**
+ * Following function takes a seed and an edited tile map, and creates a
+ * JSLevel whose changedBlocks are every block differing from the seed's
+ * natural generation, the same level generate_saved_game_from_seed gives.
+ * The changedBlocks are built directly, without going through json
+ */
+pub fn level_from_edited_map (seed: i64, edited: &[u8]) -> Result<JSLevel, ClassicJsError> {
+
+    let world_size: i32 = world_size_of_tile_map(edited)?;
+
+    let natural: Vec<u8> = get_tile_map(world_size, seed);
+
+    let mut changed_blocks: HashMap<String, ChangedBlocks> = HashMap::new();
+    for (i, (bt, natural_bt)) in edited.iter().zip(natural.iter()).enumerate() {
+        if bt != natural_bt {
+            //Tilemaps are stored in X,Z,Y format
+            let i: i32 = i as i32;
+            let (x, z, y): (i32, i32, i32) = (i % world_size, (i / world_size) % world_size, i / (world_size * world_size));
+            changed_blocks.insert(format!("p{}_{}_{}", x, y, z), ChangedBlocks::new(1, *bt));
+        }
+    }
+
+    let mut output: JSLevel = JSLevel::new(seed, changed_blocks, world_size, 1);
+    output.spawn = Some(spawn_point(edited, world_size));
+
+    return Ok(output);
+
+}
+
+/**
+ * Same as generate_saved_game_from_seed, but the natural generation of
+ * the seed is looked up in the cache instead of being regenerated
+ */
+pub fn generate_saved_game_from_seed_cached (seed: i64, tile_map: Vec<u8>, cache: &mut TileMapCache) -> Result<JSLevel, ClassicJsError> {
+
+    let world_size: i32 = world_size_of_tile_map(&tile_map)?;
+    let changed_blocks: HashMap<String, ChangedBlocks> = HashMap::new();
+    let level = JSLevel::new(seed, changed_blocks, world_size, 1);
+
+    let spawn: (i32, i32, i32) = spawn_point(&tile_map, world_size);
+
+    let mut output: JSLevel = deserialize_saved_game(serialize_saved_game_cached(level, tile_map, DEFAULT_OPTIMIZATION.opt(), cache)?);
+    output.spawn = Some(spawn);
+
+    return Ok(output);
+
+}
+
+/**
+ * Following function returns the block natural generation places at
+ * x, y, z for the seed. None is returned for world sizes that aren't one
+ * of the WORLD_SIZES and for coordinates outside of the world. Every stage
+ * of world generation draws from the same random numbers, so a single
+ * block can't be generated on its own, and this generates the whole world,
+ * taking as long as get_tile_map. Use natural_block_at_cached when looking
+ * up many blocks of the same world
+ */
+pub fn natural_block_at (world_size: i32, seed: i64, x: i32, y: i32, z: i32) -> Option<u8> {
+    return natural_block_at_cached(world_size, seed, x, y, z, &mut TileMapCache::new());
+}
+
+/**
+ * Same as natural_block_at, but the natural generation of the seed is
+ * looked up in the cache, so only the first block of a world generates it
+ * and later blocks are a lookup
+ */
+pub fn natural_block_at_cached (world_size: i32, seed: i64, x: i32, y: i32, z: i32, cache: &mut TileMapCache) -> Option<u8> {
+    if !WORLD_SIZES.contains(&world_size) { return None }
+    if !(0..world_size).contains(&x) || !(0..64).contains(&y) || !(0..world_size).contains(&z) { return None }
+
+    return block_at(cache.get_or_generate(world_size, seed), world_size, x, y, z);
+}
+
+/**
+ * Following function generates a world with the given generator and
+ * creates a JSLevel from it. As the browser always regenerates the world
+ * with its own generation, every block the generator places differently
+ * is stored as a changed block
+ */
+pub fn world_from_seed (seed: i64, world_size: i32, generator: &dyn WorldGenerator) -> Result<JSLevel, ClassicJsError> {
+    return generate_saved_game_from_seed(seed, generator.generate(world_size, seed));
+}
+
+/**
+ * TileMapCache struct stores natural tile maps keyed by world size and
+ * seed, so a tool serializing many edits of the same world only pays
+ * for the world generation once. Each 512 world takes 16MB, so clear
+ * the cache when done with a world
+ */
+#[derive(Debug, Clone, Default)]
+pub struct TileMapCache {
+    maps: HashMap<(i32, i64), Vec<u8>>
+}
+
+impl TileMapCache {
+    pub fn new () -> Self {
+        TileMapCache { maps: HashMap::new() }
+    }
+
+    /**
+     * Returns the natural tile map for the world size and seed,
+     * generating it first if it is not cached yet
+     */
+    pub fn get_or_generate (&mut self, world_size: i32, seed: i64) -> &[u8] {
+        return self.maps.entry((world_size, seed)).or_insert_with(|| get_tile_map(world_size, seed));
+    }
+
+    pub fn len (&self) -> usize {
+        return self.maps.len();
+    }
+
+    pub fn is_empty (&self) -> bool {
+        return self.maps.is_empty();
+    }
+
+    pub fn clear (&mut self) {
+        self.maps.clear();
+    }
+}
+
+//Natural tile map of the world the serializers generated last, so serializing
+//the same world again, e.g. saving on every edit, doesn't regenerate it
+static GENERATION_CACHE: Mutex<Option<CachedTileMap>> = Mutex::new(None);
+
+//World size and seed of a cached natural tile map, along with the tile map
+type CachedTileMap = ((i32, i64), Arc<Vec<u8>>);
+
+/**
+ * Following function returns the natural tile map for the world size and
+ * seed from the generation cache, generating it and replacing the cached
+ * world if it is another one. Only one world is kept, as a 512 world is 16MB
+ */
+fn natural_tile_map (world_size: i32, seed: i64) -> Arc<Vec<u8>> {
+    if let Some((key, tile_map)) = &*GENERATION_CACHE.lock().unwrap_or_else(|e| e.into_inner()) {
+        if *key == (world_size, seed) { return Arc::clone(tile_map) }
+    }
+
+    //Generating without holding the lock, so other worlds aren't kept waiting
+    let tile_map: Arc<Vec<u8>> = Arc::new(get_tile_map(world_size, seed));
+    *GENERATION_CACHE.lock().unwrap_or_else(|e| e.into_inner()) = Some(((world_size, seed), Arc::clone(&tile_map)));
+
+    return tile_map;
+}
+
+/**
+ * Following function returns the natural tile map for the world size and
+ * seed as a shared Arc, so threads diffing against the same world share
+ * one copy instead of cloning it, e.g. passing it to
+ * serialize_saved_game_with_natural. It goes through the same cache as
+ * serialize_saved_game, so asking for the last world again is a lookup
+ */
+pub fn get_tile_map_arc (world_size: i32, seed: i64) -> Arc<Vec<u8>> {
+    return natural_tile_map(world_size, seed);
+}
+
+/**
+ * Following function drops the natural tile map serialize_saved_game keeps
+ * of the last world it serialized, freeing its memory once no Arc from
+ * get_tile_map_arc holds it either
+ */
+pub fn clear_generation_cache () {
+    *GENERATION_CACHE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/**
+ * WorldGenerator trait is implemented by anything that can generate a
+ * tile map for a world size and seed, so worlds from custom generators
+ * can be serialized and written the same way as the game's own.
+ * Tile maps must be world_size * 64 * world_size long, in X,Z,Y order
+ */
+pub trait WorldGenerator {
+    fn generate (&self, world_size: i32, seed: i64) -> Vec<u8>;
+}
+
+/**
+ * ClassicGenerator struct is the game's own world generation
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClassicGenerator;
+
+impl WorldGenerator for ClassicGenerator {
+    fn generate (&self, world_size: i32, seed: i64) -> Vec<u8> {
+        return get_tile_map(world_size, seed);
+    }
+}
+
+impl WorldGenerator for GenerationParams {
+    fn generate (&self, world_size: i32, seed: i64) -> Vec<u8> {
+        return get_tile_map_with_params(world_size, seed, self);
+    }
+}
+
 /**
  * Following function accepts a world size and seed,
  * and then passes them to the js world generation 
  * functionality, and then returns the output as a Vec<>
  */
 pub fn get_tile_map (world_size: i32, seed: i64) -> Vec<u8> {
-    let y: i32 = 64;
     let level: HashMap<usize, u8> = random_level_worker::start_generation(world_size, seed); //Generating hashmap of all tiles in the world
-    let mut tile_map: Vec<u8> = Vec::new();
+    return tiles_to_tile_map(world_size, level);
+}
+
+/**
+ * Following function returns the tile map of a world as an iterator over
+ * its 64 horizontal layers, from y 0 up, each worldSize * worldSize blocks
+ * in X,Z order. Concatenated they equal get_tile_map. World generation
+ * floods, erodes and grows trees across the whole world, so the world is
+ * still generated in one go, when the first layer is asked for, but each
+ * layer is handed out on its own to consumers processing one at a time
+ */
+pub fn generate_layers (world_size: i32, seed: i64) -> impl Iterator<Item = Vec<u8>> {
+    let layer_len: usize = (world_size * world_size) as usize;
+    let mut tile_map: Option<Vec<u8>> = None;
+
+    return (0..64).map(move |y| {
+        let tile_map: &Vec<u8> = tile_map.get_or_insert_with(|| get_tile_map(world_size, seed));
+        tile_map[y * layer_len..(y + 1) * layer_len].to_vec()
+    });
+}
+
+/**
+ * Following function returns only the top depth layers of a world, e.g.
+ * for a map preview, from y 64 - depth up, in the X,Z,Y order of
+ * get_tile_map. It equals the end of get_tile_map for the same seed.
+ * depth is clamped to 0..=64. Every stage of world generation draws from
+ * the same random numbers, so the whole world is still generated, but
+ * only the top layers are kept
+ */
+pub fn generate_surface_only (world_size: i32, seed: i64, depth: i32) -> Vec<u8> {
+    let layer_len: usize = (world_size * world_size) as usize;
+    let depth: usize = depth.clamp(0, 64) as usize;
+
+    let mut tile_map: Vec<u8> = get_tile_map(world_size, seed);
+    tile_map.drain(..(64 - depth) * layer_len);
+
+    return tile_map;
+}
+
+/**
+ * Following function generates the tile maps of successive seeds from
+ * start_seed, and returns the first seed whose tile map and world size
+ * satisfy the predicate. None is returned if none of the max_tries seeds do.
+ * Every try generates a whole world, so keep max_tries small for 512 worlds
+ */
+pub fn find_seed<F: Fn(&[u8], i32) -> bool> (world_size: i32, start_seed: i64, max_tries: usize, predicate: F) -> Option<i64> {
+    for i in 0..max_tries as i64 {
+        let seed: i64 = start_seed.checked_add(i)?;
+
+        if predicate(&get_tile_map(world_size, seed), world_size) { return Some(seed) }
+    }
+
+    return None;
+}
+
+/**
+ * Following function accepts a world size, seed and generation
+ * parameters, and then passes them to the js world generation
+ * functionality, and then returns the output as a Vec<>.
+ * The default parameters generate the same world as get_tile_map
+ */
+pub fn get_tile_map_with_params (world_size: i32, seed: i64, params: &GenerationParams) -> Vec<u8> {
+    let level: HashMap<usize, u8> = random_level_worker::start_generation_with_params(world_size, seed, params.clone()); //Generating hashmap of all tiles in the world
+    return tiles_to_tile_map(world_size, level);
+}
+
+/**
+ * Following function copies the hashmap of tiles produced by the
+ * world generation into a tile map Vec<>
+ */
+fn tiles_to_tile_map (world_size: i32, level: HashMap<usize, u8>) -> Vec<u8> {
+    let mut tile_map: Vec<u8> = Vec::with_capacity(tile_map_len(world_size));
 
-    for i in 0..world_size * y * world_size {
-        tile_map.push(level.get(&(i as usize)).copied().unwrap_or(0)); //Copying hashmap to vec
+    for i in 0..tile_map_len(world_size) {
+        tile_map.push(level.get(&i).copied().unwrap_or(0)); //Copying hashmap to vec
     }
 
     return tile_map
@@ -613,7 +2608,7 @@ pub fn serialize_saved_game_from_seed (seed: i64, tile_map: Vec<u8>) -> String {
     let changed_blocks: HashMap<String, ChangedBlocks> = HashMap::new();
     let level = JSLevel::new(seed, changed_blocks, world_size, 1);
 
-    return serialize_saved_game(level, tile_map, 2);
+    return serialize_saved_game(level, tile_map, 2).expect("tile map is not a square world 64 blocks high");
 }
 
 /*/**
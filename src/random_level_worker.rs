@@ -1,4 +1,4 @@
-/**
+/*!
  * This is the ported world generation code from classic js
  * Credit to TheSunCat for the deobfuscated js code - see js source here: 
  * https://github.com/TheSunCat/Minecraft-Classic-Reversed/blob/master/assets/js/RandomLevelWorker.js
@@ -22,9 +22,60 @@
  * is so minor it has not been noticeable in testing.
  */
 
+// Kept as close to the js source as possible, so these lints are allowed on purpose
+#![allow(
+    clippy::approx_constant,
+    clippy::assign_op_pattern,
+    clippy::int_plus_one,
+    clippy::identity_op,
+    clippy::needless_late_init,
+    clippy::precedence,
+    clippy::unnecessary_cast,
+    unused_assignments,
+    unused_variables
+)]
+
 use crate::random::Random;
 use std::collections::HashMap;
 
+/**
+ * GenerationParams struct stores the knobs for world generation, the
+ * default values reproduce the game's own generation exactly
+ * ground_level: Height the terrain is raised around, 32 by default
+ * surface_tile: Tile placed on exposed dirt, grass (1) by default
+ * beach_tile: Tile placed on exposed dirt near water, sand (11) by default
+ * caves: Whether caves are carved out
+ * ores: Whether coal, iron and gold are placed
+ * tree_density: Multiplier on the number of tree clusters, 0 for no trees
+ * 
+ * The water level is not configurable, as the flood fill relies on it
+ * Any change away from the defaults changes how the random numbers are
+ * consumed, so later stages will differ from the game, but generation
+ * stays deterministic for a given seed and set of params
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationParams {
+    pub ground_level: i32,
+    pub surface_tile: u8,
+    pub beach_tile: u8,
+    pub caves: bool,
+    pub ores: bool,
+    pub tree_density: f64
+}
+
+impl Default for GenerationParams {
+    fn default () -> Self {
+        GenerationParams {
+            ground_level: 32,
+            surface_tile: 1,
+            beach_tile: 11,
+            caves: true,
+            ores: true,
+            tree_density: 1.0
+        }
+    }
+}
+
 //Creating the Distort struct
 struct Distort {
     source: PerlinNoise,
@@ -173,13 +224,14 @@ struct RandomLevel {
     random: Random,
     rand: f64,
     tiles: HashMap<usize, u8>,
-    fill_queue: HashMap<usize, i32>
+    fill_queue: HashMap<usize, i32>,
+    params: GenerationParams
 
 }
 
 impl RandomLevel {
 
-    pub fn new (seed: i64, x_size: i32, z_size: i32, mut y_size: i32) -> RandomLevel {
+    pub fn new (seed: i64, x_size: i32, z_size: i32, mut y_size: i32, params: GenerationParams) -> RandomLevel {
         let progress_string: String = String::from("");
         let progress_percent: i32 = 0;
         let progress_tiles: HashMap<usize, u8> = HashMap::new();
@@ -201,7 +253,8 @@ impl RandomLevel {
             random,
             rand,
             tiles,
-            fill_queue
+            fill_queue,
+            params
         }
 
     }
@@ -237,10 +290,10 @@ impl RandomLevel {
                 }
 
                 if l1 == 0 {
-                    let mut i2: i32 = 1;//Tile.grass.id;
+                    let mut i2: i32 = self.params.surface_tile as i32;//Tile.grass.id;
 
                     if j1 <= k / 2 - 1 && flag {
-                        i2 = 11;//Tile.sand.id;
+                        i2 = self.params.beach_tile as i32;//Tile.sand.id;
                     }
 
                     self.tiles.insert(k1 as usize, i2 as u8);
@@ -278,7 +331,7 @@ impl RandomLevel {
     //plant
     pub fn plant (&mut self, aint: HashMap<usize, f64>) {
         let i: i32 = self.x_size;
-        let j: f64 = self.x_size as f64 * self.z_size as f64 / 4000.0;
+        let j: f64 = self.x_size as f64 * self.z_size as f64 / 4000.0 * self.params.tree_density;
 
         let mut k: f64=0.0;
         while k < j {
@@ -500,8 +553,6 @@ impl RandomLevel {
 
             k2 += j3 - i3;
 
-            i3 = i3;
-
             while i3 < j3 {
 
                 self.tiles.insert(val as usize, tile);
@@ -659,7 +710,7 @@ impl RandomLevel {
             i1 = 0;
             while i1 < k2 {
                 l1 = (perlinnoise1.get_value( l as f64, i1 as f64) / 24.0) - 4.0;
-                i2 = aint1.get(&((l + i1 * j2) as usize)).copied().unwrap_or(0.0) + j1 as f64 / 2.0;
+                i2 = aint1.get(&((l + i1 * j2) as usize)).copied().unwrap_or(0.0) + self.params.ground_level as f64; //j1 / 2
                 l2 = i2 + l1;
                 
                 aint.insert((l + i1 * j2) as usize, f64::max(i2, l2));
@@ -693,72 +744,76 @@ impl RandomLevel {
         k1 = self.y_size;
         l = k2 * j1 * k1 / 256 / 64;
 
-        i1 = 0;
-        while i1 < l {
-            //progress(i1 * 100 / (l - 1) / 4);
-            self.progress_percent = i1 * 100 / (l - 1) / 4;
-            //self.postMessage(progress);
+        if self.params.caves {
+            i1 = 0;
+            while i1 < l {
+                //progress(i1 * 100 / (l - 1) / 4);
+                self.progress_percent = i1 * 100 / (l - 1) / 4;
+                //self.postMessage(progress);
 
-            let mut f1: f64 = self.random.next_float() * k2 as f64;
-            let mut f2: f64 = self.random.next_float() * k1 as f64;
-            let mut f3: f64 = self.random.next_float() * j1 as f64;
-
-            i3 = (self.random.next_float() + self.random.next_float()) * 75.0;
-            let mut f4: f64 = self.random.next_float() * 3.141592653589793 * 2.0;
-            let mut f5: f64 = 0.0;
-            let mut f6: f64 = self.random.next_float() * 3.141592653589793 * 2.0;
-            let mut f7: f64 = 0.0;
-
-            let mut l3: f64 = 0.0;
-            while l3 < i3 {
-                f1 = f1 + f64::sin(f4) * f64::cos(f6);
-                f3 = f3 + f64::cos(f4) * f64::cos(f6);
-                f2 = f2 + f64::sin(f6);
-                f4 += f5 * 0.2;
-                f5 *= 0.9;
-                f5 = f5 + (self.random.next_float() - self.random.next_float());
-                f6 = (f6 + f7 * 0.5) * 0.5;
-                f7 *= 0.9;
-                f7 = f7 + (self.random.next_float() - self.random.next_float());
-                if self.random.next_float() >= 0.3 {
-                    let f8: f64 = f1 + self.random.next_float() * 4.0 - 2.0;
-                    let f9: f64 = f2 + self.random.next_float() * 4.0 - 2.0;
-                    let f10: f64 = f3 + self.random.next_float() * 4.0 - 2.0;
-                    let f11: f64 = f64::sin( l3 * 3.141592653589793 / i3) * 2.5 + 1.0;
-
-                    let mut i4: i32 = (f8 - f11) as i32; //parseInt()
-                    while i4 <= (f8 + f11) as i32 { //parseInt()
-                        let mut j4: i32 = (f9 - f11) as i32; //parseInt()
-                        while j4 <= (f9 + f11) as i32 { //parseInt()
-                            let mut k4: f64 = f10 - f11;
-                            while k4 <= (f10 + f11) {
-                                let f12: f64 = i4 as f64 - f8;
-                                let f13: f64 = j4 as f64 - f9;
-                                let f14: f64 = k4 - f10;
-
-                                if f12 * f12 + f13 * f13 * 2.0 + f14 * f14 < f11 * f11 && i4 >= 1 && j4 >= 1 && k4 >= 1.0 && i4 < self.x_size - 1 && j4 < self.y_size - 1 && k4 < self.z_size as f64 - 1.0 {
-                                    let l4: i32 = (((j4 as f64 * self.z_size as f64 + k4) * self.x_size as f64) + i4 as f64) as i32;//parseInt()
-
-                                    //if (tiles[l4] == Tile.rock.id) {
-                                    if self.tiles.get(&(l4 as usize)).copied().unwrap_or(0) == 2 {
-                                        self.tiles.insert(l4 as usize, 0);
+                let mut f1: f64 = self.random.next_float() * k2 as f64;
+                let mut f2: f64 = self.random.next_float() * k1 as f64;
+                let mut f3: f64 = self.random.next_float() * j1 as f64;
+
+                i3 = (self.random.next_float() + self.random.next_float()) * 75.0;
+                let mut f4: f64 = self.random.next_float() * 3.141592653589793 * 2.0;
+                let mut f5: f64 = 0.0;
+                let mut f6: f64 = self.random.next_float() * 3.141592653589793 * 2.0;
+                let mut f7: f64 = 0.0;
+
+                let mut l3: f64 = 0.0;
+                while l3 < i3 {
+                    f1 = f1 + f64::sin(f4) * f64::cos(f6);
+                    f3 = f3 + f64::cos(f4) * f64::cos(f6);
+                    f2 = f2 + f64::sin(f6);
+                    f4 += f5 * 0.2;
+                    f5 *= 0.9;
+                    f5 = f5 + (self.random.next_float() - self.random.next_float());
+                    f6 = (f6 + f7 * 0.5) * 0.5;
+                    f7 *= 0.9;
+                    f7 = f7 + (self.random.next_float() - self.random.next_float());
+                    if self.random.next_float() >= 0.3 {
+                        let f8: f64 = f1 + self.random.next_float() * 4.0 - 2.0;
+                        let f9: f64 = f2 + self.random.next_float() * 4.0 - 2.0;
+                        let f10: f64 = f3 + self.random.next_float() * 4.0 - 2.0;
+                        let f11: f64 = f64::sin( l3 * 3.141592653589793 / i3) * 2.5 + 1.0;
+
+                        let mut i4: i32 = (f8 - f11) as i32; //parseInt()
+                        while i4 <= (f8 + f11) as i32 { //parseInt()
+                            let mut j4: i32 = (f9 - f11) as i32; //parseInt()
+                            while j4 <= (f9 + f11) as i32 { //parseInt()
+                                let mut k4: f64 = f10 - f11;
+                                while k4 <= (f10 + f11) {
+                                    let f12: f64 = i4 as f64 - f8;
+                                    let f13: f64 = j4 as f64 - f9;
+                                    let f14: f64 = k4 - f10;
+
+                                    if f12 * f12 + f13 * f13 * 2.0 + f14 * f14 < f11 * f11 && i4 >= 1 && j4 >= 1 && k4 >= 1.0 && i4 < self.x_size - 1 && j4 < self.y_size - 1 && k4 < self.z_size as f64 - 1.0 {
+                                        let l4: i32 = (((j4 as f64 * self.z_size as f64 + k4) * self.x_size as f64) + i4 as f64) as i32;//parseInt()
+
+                                        //if (tiles[l4] == Tile.rock.id) {
+                                        if self.tiles.get(&(l4 as usize)).copied().unwrap_or(0) == 2 {
+                                            self.tiles.insert(l4 as usize, 0);
+                                        }
                                     }
+                                    k4 += 1.0;
                                 }
-                                k4 += 1.0;
+                                j4 += 1;
                             }
-                            j4 += 1;
+                            i4 += 1;
                         }
-                        i4 += 1;
                     }
+                    l3 += 1.0;
                 }
-                l3 += 1.0;
+                i1 += 1;
             }
-            i1 += 1;
         }
         
-        self.place_ore(20, 90.0, 1.0, 4.0); // coal - Known Issue that Ore Populates Incorrectly
-        self.place_ore(19, 70.0, 2.0, 4.0); // iron - Known Issue that Ore Populates Incorrectly
-        self.place_ore(18, 50.0, 3.0, 4.0); // gold - Known Issue that Ore Populates Incorrectly
+        if self.params.ores {
+            self.place_ore(20, 90.0, 1.0, 4.0); // coal - Known Issue that Ore Populates Incorrectly
+            self.place_ore(19, 70.0, 2.0, 4.0); // iron - Known Issue that Ore Populates Incorrectly
+            self.place_ore(18, 50.0, 3.0, 4.0); // gold - Known Issue that Ore Populates Incorrectly
+        }
 
         self.progress_string = String::from("Watering..");
         //this.progressRenderer.progressStage("Watering..");
@@ -826,12 +881,16 @@ impl RandomLevel {
 }
 
 pub fn start_generation (world_size: i32, seed: i64) -> HashMap<usize, u8> { //{worldSize: worldSize, seed: props.seed, seedrandom: seedrandom}
+    return start_generation_with_params(world_size, seed, GenerationParams::default());
+}
+
+pub fn start_generation_with_params (world_size: i32, seed: i64, params: GenerationParams) -> HashMap<usize, u8> {
     
     let width: i32 = world_size;
     let depth: i32 = world_size;
     let height: i32 = 64;
 
-    let mut level = RandomLevel::new(seed, width, depth, height);
+    let mut level = RandomLevel::new(seed, width, depth, height, params);
     level.create_level();
     return level.progress_tiles;
 }
\ No newline at end of file
@@ -0,0 +1,46 @@
+/*!
+ * Reading localStorage from Safari. WebKit keeps each origin's localStorage
+ * in its own sqlite file under ~/Library/Safari/LocalStorage, in a table of
+ * type:
+ * CREATE TABLE ItemTable (key TEXT UNIQUE ON CONFLICT REPLACE, value BLOB NOT NULL ON CONFLICT FAIL)
+ * Unlike Firefox the values are not compressed, they are stored as raw
+ * UTF-16 (little endian) bytes
+ */
+
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+
+use crate::ClassicJsError;
+
+/**
+ * Following function opens a WebKit localStorage database at the provided
+ * path, then retreives the specified object and decodes it from UTF-16.
+ * None is returned if the object is not stored in the database, and a
+ * missing file is an error rather than being created
+ */
+pub fn read_from_safari (file_path: String, object: &str) -> Result<Option<String>, ClassicJsError> {
+
+    //Opening read only, so a wrong path isn't created as a blank database
+    let conn: Connection = Connection::open_with_flags(&file_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let value: Option<Vec<u8>> = conn.query_row(
+        "SELECT value FROM ItemTable where key=?1;",
+        [object],
+        |row| row.get(0)
+    ).optional()?;
+
+    let bytes: Vec<u8> = match value {
+        Some(bytes) => bytes,
+        None => return Ok(None)
+    };
+
+    //Pairing the bytes back up into UTF-16 code units
+    let pairs = bytes.chunks_exact(2);
+    if !pairs.remainder().is_empty() { return Err(ClassicJsError::InvalidUtf16(String::from(object))) }
+    let units: Vec<u16> = pairs.map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+
+    return match String::from_utf16(&units) {
+        Ok(string) => Ok(Some(string)),
+        Err(_) => Err(ClassicJsError::InvalidUtf16(String::from(object)))
+    };
+
+}
@@ -0,0 +1,43 @@
+use mc_classic_js::{local_storage_command, parse_set_item_command, ClassicJsError};
+
+#[test]
+fn local_storage_command_sets_each_key_once () {
+    let command: String = local_storage_command(&[String::from("{}"), String::from("[]")]);
+
+    assert_eq!(command.matches(r#"localStorage.setItem("savedGame", "#).count(), 1);
+    assert_eq!(command.matches(r#"localStorage.setItem("settings", "#).count(), 1);
+    assert_eq!(command, r#"localStorage.setItem("savedGame", `{}`);localStorage.setItem("settings", `[]`)"#);
+}
+
+#[test]
+fn parse_set_item_command_reads_back_local_storage_command () {
+    let json_strings: [String; 2] = [String::from(r#"{"worldSeed":1,"changedBlocks":{}}"#), String::from(r#"{"music":true}"#)];
+    let command: String = local_storage_command(&json_strings);
+
+    assert_eq!(parse_set_item_command(&command).unwrap(), vec![
+        (String::from("savedGame"), json_strings[0].clone()),
+        (String::from("settings"), json_strings[1].clone())
+    ]);
+}
+
+#[test]
+fn parse_set_item_command_unescapes_backticks () {
+    let command: &str = "localStorage.setItem( 'savedGame' , ` a\\`b\\n` );\n";
+
+    assert_eq!(parse_set_item_command(command).unwrap(), vec![(String::from("savedGame"), String::from(" a`b\\n"))]);
+    assert!(matches!(parse_set_item_command("localStorage.setItem(\"savedGame\", `{}"), Err(ClassicJsError::InvalidCommand(_))));
+    assert!(matches!(parse_set_item_command("localStorage.getItem(\"savedGame\")"), Err(ClassicJsError::InvalidCommand(_))));
+}
+
+#[test]
+fn seed_to_command_makes_a_world_for_the_seed () {
+    use mc_classic_js::{read_level_header, seed_to_command};
+
+    let command: String = seed_to_command(1234, 256, None).unwrap();
+    let pairs: Vec<(String, String)> = parse_set_item_command(&command).unwrap();
+
+    assert_eq!(pairs.len(), 1);
+    assert_eq!(pairs[0].0, "savedGame");
+    assert_eq!(read_level_header(&pairs[0].1).unwrap(), (1234, 256, 1));
+    assert!(matches!(seed_to_command(1234, 100, None), Err(ClassicJsError::InvalidWorldSize(100))));
+}
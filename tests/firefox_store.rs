@@ -0,0 +1,488 @@
+#![cfg(feature = "sqlite")]
+
+use mc_classic_js::*;
+
+use std::collections::HashMap;
+
+use tempfile::TempDir;
+
+const WEBSITE: &str = "https://classic.minecraft.net";
+
+fn db_path (base: &str) -> String {
+    format!("{}/{}/ls/data.sqlite", base, origin_to_dir(WEBSITE))
+}
+
+//A temporary folder to write stores into, kept until the TempDir is dropped
+fn temp_base () -> (TempDir, String) {
+    let dir: TempDir = tempfile::tempdir().unwrap();
+    let base: String = dir.path().to_str().unwrap().to_string();
+    (dir, base)
+}
+
+//A default level as json
+fn default_level_str () -> String {
+    serde_json::to_string(&JSLevel::default()).unwrap()
+}
+
+//A default level and default settings, as json
+fn default_json_strings () -> [String; 2] {
+    [default_level_str(), serialize_settings(Settings::default())]
+}
+
+//A store for WEBSITE holding default_json_strings
+fn default_store () -> (TempDir, String) {
+    let (dir, base) = temp_base();
+    write_data(base.clone(), default_json_strings(), Origin::parse(WEBSITE).unwrap()).unwrap();
+    (dir, base)
+}
+
+#[test]
+fn write_data_round_trips_through_read_data () {
+    let (_dir, base) = temp_base();
+
+    let mut changed_blocks: HashMap<String, ChangedBlocks> = HashMap::new();
+    changed_blocks.insert(String::from("p1_30_2"), ChangedBlocks::new(1, 4));
+    changed_blocks.insert(String::from("p127_0_64"), ChangedBlocks::new(1, 0));
+    let level: JSLevel = JSLevel::new(123456, changed_blocks, 128, 1);
+
+    let settings: Settings = Settings { username: String::from("builder"), drawDistance: 2, ..Settings::default() };
+
+    let level_str: String = serde_json::to_string(&level).unwrap();
+    write_data(base.clone(), [level_str, serialize_settings(settings.clone())], Origin::parse(WEBSITE).unwrap()).unwrap();
+
+    let data: Data = read_data(db_path(&base)).unwrap();
+
+    assert_eq!(data.js_level, level);
+    assert_eq!(data.settings, settings);
+}
+
+#[test]
+fn write_data_metadata_reads_back () {
+    let (_dir, base) = default_store();
+
+    let metadata: MetadataV2 = read_metadata_v2(format!("{}/{}/.metadata-v2", base, origin_to_dir(WEBSITE))).unwrap();
+
+    assert_eq!(metadata.origin, WEBSITE);
+    assert!(metadata.timestamp > 0);
+    assert!(!metadata.persisted);
+    assert!(!metadata.is_app);
+}
+
+#[test]
+fn metadata_v2_matches_firefox_layout () {
+    let (_dir, base) = default_store();
+
+    let bytes: Vec<u8> = std::fs::read(format!("{}/{}/.metadata-v2", base, origin_to_dir(WEBSITE))).unwrap();
+
+    //Layout of Firefox's CreateDirectoryMetadata2 after the u64 timestamp
+    let mut expected: Vec<u8> = vec![
+        0, //Persisted
+        0, 0, 0, 0, //Reserved data 1
+        0, 0, 0, 0, //Reserved data 2
+        0, 0, 0, 0, //Suffix
+        0, 0, 0, 13 //Group
+    ];
+    expected.extend_from_slice(b"minecraft.net");
+    expected.extend_from_slice(&[0, 0, 0, 29]); //Origin
+    expected.extend_from_slice(b"https://classic.minecraft.net");
+    expected.push(0); //Is App
+
+    assert_eq!(&bytes[8..], &expected[..]);
+}
+
+#[test]
+fn emoji_username_round_trips () {
+    let (_dir, base) = temp_base();
+
+    let settings: Settings = Settings { username: String::from("🧱builder"), ..Settings::default() };
+    let settings_str: String = serialize_settings(settings.clone());
+
+    let level_str: String = default_level_str();
+    write_data(base.clone(), [level_str, settings_str.clone()], Origin::parse(WEBSITE).unwrap()).unwrap();
+
+    let data: Data = read_data(db_path(&base)).unwrap();
+    assert_eq!(data.settings.username, "🧱builder");
+
+    //The brick is a surrogate pair, so it counts as two code units
+    let entry: LocalStorageEntry = read_entry(db_path(&base), "settings").unwrap().unwrap();
+    assert_eq!(entry.utf16_length as usize, settings_str.encode_utf16().count());
+    assert_eq!(entry.utf16_length as usize, settings_str.chars().count() + 1);
+}
+
+#[test]
+fn set_username_keeps_the_saved_game () {
+    let (_dir, base) = temp_base();
+
+    let level: JSLevel = JSLevel::new(99, HashMap::from([(String::from("p3_40_3"), (1, 20).into())]), 128, 1);
+    let level_str: String = serde_json::to_string(&level).unwrap();
+    write_data(base.clone(), [level_str, serialize_settings(Settings::default())], Origin::parse(WEBSITE).unwrap()).unwrap();
+
+    set_username(db_path(&base), String::from("renamed")).unwrap();
+
+    let data: Data = read_data(db_path(&base)).unwrap();
+    assert_eq!(data.settings, Settings { username: String::from("renamed"), ..Settings::default() });
+    assert_eq!(data.js_level, level);
+}
+
+#[test]
+fn set_username_leaves_missing_stores_alone () {
+    let dir = tempfile::tempdir().unwrap();
+    let path: String = dir.path().join("data.sqlite").to_str().unwrap().to_string();
+
+    assert!(set_username(path.clone(), String::from("renamed")).is_err());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn set_username_keeps_the_settings_key () {
+    let (_dir, base) = temp_base();
+
+    let level_str: String = serialize_saved_game_sorted(&JSLevel::new(1, HashMap::new(), 128, 1));
+    let options: WriteOptions = WriteOptions { keys: [String::from("savedGame"), String::from("Settings")], ..WriteOptions::default() };
+    write_data_with_options(base.clone(), [level_str, serialize_settings(Settings::default())], Origin::parse(WEBSITE).unwrap(), &options).unwrap();
+
+    set_username(db_path(&base), String::from("renamed")).unwrap();
+
+    assert!(read_entry(db_path(&base), "settings").unwrap().is_none());
+    let settings: Settings = serde_json::from_str(&read_settings(db_path(&base)).unwrap()).unwrap();
+    assert_eq!(settings.username, "renamed");
+}
+
+#[test]
+fn set_username_escapes_the_username () {
+    let (_dir, base) = default_store();
+
+    let username: String = String::from(r#"a"b\c"#);
+    set_username(db_path(&base), username.clone()).unwrap();
+
+    assert_eq!(read_data(db_path(&base)).unwrap().settings.username, username);
+
+    //Key bindings are escaped the same way
+    let settings: Settings = Settings { chat: String::from("\\"), fog: String::from("\""), ..Settings::default() };
+    assert_eq!(serde_json::from_str::<Settings>(&serialize_settings(settings.clone())).unwrap(), settings);
+}
+
+#[test]
+fn set_username_keeps_unknown_settings () {
+    let (_dir, base) = temp_base();
+
+    //Settings written by a newer game with a key Settings doesn't have
+    let settings_str: String = serialize_settings(Settings::default()).replacen('{', r#"{"sensitivity":0.5,"#, 1);
+    let level_str: String = default_level_str();
+    write_data(base.clone(), [level_str, settings_str], Origin::parse(WEBSITE).unwrap()).unwrap();
+
+    set_username(db_path(&base), String::from("renamed")).unwrap();
+
+    let settings: Settings = read_data(db_path(&base)).unwrap().settings;
+    assert_eq!(settings.username, "renamed");
+    assert_eq!(settings.extra.get("sensitivity"), Some(&serde_json::json!(0.5)));
+    assert!(read_settings(db_path(&base)).unwrap().ends_with(r#""username":"renamed","sensitivity":0.5}"#));
+}
+
+#[test]
+fn write_data_sets_the_pragmas () {
+    let (_dir, base) = default_store();
+
+    let conn: rusqlite::Connection = rusqlite::Connection::open(db_path(&base)).unwrap();
+    let pragma = |name: &str| conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get::<_, i64>(0)).unwrap();
+
+    assert_eq!(pragma("user_version"), 80);
+    assert_eq!(pragma("auto_vacuum"), 2);
+    assert_eq!(pragma("page_size"), 1024);
+}
+
+#[test]
+fn write_data_stores_last_access_time () {
+    let (_dir, base) = temp_base();
+
+    let [level_str, settings_str]: [String; 2] = default_json_strings();
+
+    write_data(base.clone(), [level_str.clone(), settings_str.clone()], Origin::parse(WEBSITE).unwrap()).unwrap();
+    assert!(read_entry(db_path(&base), "savedGame").unwrap().unwrap().last_access_time > 0);
+
+    let options: WriteOptions = WriteOptions { overwrite: true, last_access_time: Some(1_700_000_000_000_000), ..WriteOptions::default() };
+    write_data_with_options(base.clone(), [level_str, settings_str], Origin::parse(WEBSITE).unwrap(), &options).unwrap();
+    assert_eq!(read_entry(db_path(&base), "savedGame").unwrap().unwrap().last_access_time, 1_700_000_000_000_000);
+    assert_eq!(read_entry(db_path(&base), "settings").unwrap().unwrap().last_access_time, 1_700_000_000_000_000);
+}
+
+#[test]
+fn list_game_origins_finds_every_store () {
+    let (_dir, base) = temp_base();
+
+    for website in [WEBSITE, "https://omniarchive.uk:8080"] {
+        write_data(base.clone(), default_json_strings(), Origin::parse(website).unwrap()).unwrap();
+    }
+
+    //Without the metadata the origin comes from the folder name
+    std::fs::remove_file(format!("{}/https+++omniarchive.uk+8080/.metadata-v2", base)).unwrap();
+    std::fs::create_dir_all(format!("{}/https+++example.com/ls", base)).unwrap();
+
+    assert_eq!(list_game_origins(base).unwrap(), vec![WEBSITE, "https://omniarchive.uk:8080"]);
+}
+
+#[test]
+fn empty_values_are_reported () {
+    let (_dir, base) = default_store();
+
+    //Recreating the data table without its NOT NULL constraints, as other tools may write it
+    let conn: rusqlite::Connection = rusqlite::Connection::open(db_path(&base)).unwrap();
+    conn.execute_batch("CREATE TABLE loose AS SELECT * FROM data; DROP TABLE data; ALTER TABLE loose RENAME TO data;").unwrap();
+
+    //NULL and empty blobs stored as snappy compressed
+    for value in ["NULL", "x''"] {
+        conn.execute(&format!("UPDATE data SET value={} WHERE key='savedGame'", value), []).unwrap();
+        assert!(matches!(read_saved_game(db_path(&base)), Err(ClassicJsError::EmptyValue(key)) if key == "savedGame"));
+    }
+
+    //An uncompressed empty blob is an empty string
+    conn.execute("UPDATE data SET compression_type=0 WHERE key='savedGame'", []).unwrap();
+    assert_eq!(read_saved_game(db_path(&base)).unwrap(), "");
+}
+
+#[test]
+fn written_store_will_load () {
+    let (_dir, base) = default_store();
+    assert!(will_firefox_load(base.clone(), String::from(WEBSITE)).unwrap());
+
+    std::fs::remove_file(format!("{}/{}/.metadata-v2", base, origin_to_dir(WEBSITE))).unwrap();
+    assert!(!will_firefox_load(base, String::from(WEBSITE)).unwrap());
+}
+
+#[test]
+fn write_world_from_seed_writes_an_unchanged_world () {
+    let (_dir, base) = temp_base();
+
+    write_world_from_seed(base.clone(), Origin::parse(WEBSITE).unwrap(), 777, 256).unwrap();
+    assert_eq!(read_data(db_path(&base)).unwrap().js_level, JSLevel::new(777, HashMap::new(), 256, 1));
+
+    assert!(matches!(write_world_from_seed(base, Origin::parse(WEBSITE).unwrap(), 777, 100), Err(ClassicJsError::InvalidWorldSize(100))));
+}
+
+#[test]
+fn write_data_to_profile_nests_under_storage_default () {
+    let dir = tempfile::tempdir().unwrap();
+    let profile: String = dir.path().to_str().unwrap().to_string();
+
+    write_data_to_profile(profile.clone() + "/", default_json_strings(), Origin::parse(WEBSITE).unwrap()).unwrap();
+
+    assert_eq!(profile_storage_path(&profile), format!("{}/storage/default", profile));
+    assert!(std::path::Path::new(&db_path(&profile_storage_path(&profile))).is_file());
+    assert_eq!(list_game_origins(profile_storage_path(&profile)).unwrap(), vec![WEBSITE]);
+}
+
+#[test]
+fn usage_counts_keys_and_values_in_utf16 () {
+    //savedGame 9 + {} 2, settings 8 + a brick emoji as a surrogate pair 2
+    assert_eq!(compute_usage(&[("savedGame", "{}"), ("settings", "🧱")]), 21);
+
+    let (_dir, base) = default_store();
+    let json_strings: [String; 2] = default_json_strings();
+
+    let conn: rusqlite::Connection = rusqlite::Connection::open(db_path(&base)).unwrap();
+    let usage: i64 = conn.query_row("SELECT usage FROM database", [], |row| row.get(0)).unwrap();
+    assert_eq!(usage, compute_usage(&[("savedGame", &json_strings[0]), ("settings", &json_strings[1])]));
+}
+
+#[test]
+fn database_meta_reads_back () {
+    let (_dir, base) = default_store();
+    let json_strings: [String; 2] = default_json_strings();
+
+    let meta: DatabaseMeta = read_database_meta(db_path(&base)).unwrap();
+    assert_eq!(meta.origin, WEBSITE);
+    assert_eq!(meta.usage, compute_usage(&[("savedGame", &json_strings[0]), ("settings", &json_strings[1])]));
+    assert_eq!(meta.last_analyze_time, 0);
+    assert!(meta.last_vacuum_time > 0 && meta.last_vacuum_size > 0);
+}
+
+#[test]
+fn saved_game_slots_round_trip () {
+    let (_dir, base) = temp_base();
+
+    let slots: [[&str; 2]; 3] = [["savedGame", "settings"], ["savedGame2", "settings2"], ["savedGame3", "settings3"]];
+    let mut usage_entries: Vec<(String, String)> = Vec::new();
+    for (i, keys) in slots.iter().enumerate() {
+        let level: JSLevel = JSLevel::new(i as i64, HashMap::new(), 128, 1);
+        let settings: Settings = Settings { username: format!("slot{}", i), ..Settings::default() };
+        let json_strings: [String; 2] = [serialize_saved_game_sorted(&level), serialize_settings(settings)];
+        usage_entries.push((keys[0].to_string(), json_strings[0].clone()));
+        usage_entries.push((keys[1].to_string(), json_strings[1].clone()));
+
+        let options: WriteOptions = WriteOptions { overwrite: true, keys: [keys[0].to_string(), keys[1].to_string()], ..WriteOptions::default() };
+        write_data_with_options(base.clone(), json_strings, Origin::parse(WEBSITE).unwrap(), &options).unwrap();
+    }
+
+    for (i, keys) in slots.iter().enumerate() {
+        let data: Data = read_data_with_keys(db_path(&base), *keys).unwrap();
+        assert_eq!(data.js_level.worldSeed, i as i64);
+        assert_eq!(data.settings.username, format!("slot{}", i));
+    }
+    assert_eq!(read_data(db_path(&base)).unwrap().js_level.worldSeed, 0);
+
+    //Every slot counts towards the usage of the origin
+    let entries: Vec<(&str, &str)> = usage_entries.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+    assert_eq!(read_database_meta(db_path(&base)).unwrap().usage, compute_usage(&entries));
+}
+
+#[test]
+fn short_utf16_length_is_diagnosed () {
+    let (_dir, base) = temp_base();
+
+    let settings_str: String = serialize_settings(Settings { username: String::from("🧱builder"), ..Settings::default() });
+    let level_str: String = default_level_str();
+    write_data(base.clone(), [level_str, settings_str.clone()], Origin::parse(WEBSITE).unwrap()).unwrap();
+    assert!(will_firefox_load(base.clone(), String::from(WEBSITE)).unwrap());
+
+    //A length counting characters is one short for the surrogate pair of the brick
+    let conn: rusqlite::Connection = rusqlite::Connection::open(db_path(&base)).unwrap();
+    conn.execute("UPDATE data SET utf16_length = ?1 WHERE key = 'settings'", [settings_str.chars().count() as i64]).unwrap();
+
+    let findings: Vec<Diagnostic> = diagnose_store(base.clone(), String::from(WEBSITE));
+    assert!(findings.iter().any(|finding| finding.severity == Severity::Error && finding.message.starts_with("utf16_length of settings")));
+}
+
+#[test]
+fn skipping_vacuum_keeps_the_last_vacuum () {
+    let (_dir, base) = temp_base();
+
+    let json_strings: [String; 2] = default_json_strings();
+    let options: WriteOptions = WriteOptions { overwrite: true, vacuum: false, ..WriteOptions::default() };
+    write_data_with_options(base.clone(), json_strings.clone(), Origin::parse(WEBSITE).unwrap(), &options).unwrap();
+
+    //A new store still gets the pragmas without a VACUUM
+    assert!(will_firefox_load(base.clone(), String::from(WEBSITE)).unwrap());
+    let first: DatabaseMeta = read_database_meta(db_path(&base)).unwrap();
+    assert!(first.last_vacuum_time > 0);
+
+    write_data_with_options(base.clone(), json_strings, Origin::parse(WEBSITE).unwrap(), &options).unwrap();
+    let second: DatabaseMeta = read_database_meta(db_path(&base)).unwrap();
+    assert_eq!((second.last_vacuum_time, second.last_vacuum_size), (first.last_vacuum_time, first.last_vacuum_size));
+
+    vacuum_store(db_path(&base)).unwrap();
+    let vacuumed: DatabaseMeta = read_database_meta(db_path(&base)).unwrap();
+    assert!(vacuumed.last_vacuum_time > first.last_vacuum_time);
+    assert_eq!(vacuumed.usage, first.usage);
+}
+
+#[test]
+fn skipping_vacuum_writes_into_other_page_sizes () {
+    let (_dir, base) = default_store();
+
+    //A store laid out by another tool, with a page size Firefox doesn't use
+    let conn: rusqlite::Connection = rusqlite::Connection::open(db_path(&base)).unwrap();
+    conn.execute_batch("PRAGMA page_size = 4096; VACUUM;").unwrap();
+    drop(conn);
+
+    let level: JSLevel = JSLevel::new(5, HashMap::new(), 128, 1);
+    let json_strings: [String; 2] = [serialize_saved_game_sorted(&level), serialize_settings(Settings::default())];
+    let options: WriteOptions = WriteOptions { overwrite: true, vacuum: false, ..WriteOptions::default() };
+    write_data_with_options(base.clone(), json_strings, Origin::parse(WEBSITE).unwrap(), &options).unwrap();
+    assert_eq!(read_data(db_path(&base)).unwrap().js_level, level);
+
+    let page_size = || rusqlite::Connection::open(db_path(&base)).unwrap().query_row("PRAGMA page_size", [], |row| row.get::<_, i64>(0)).unwrap();
+    assert_eq!(page_size(), 4096);
+
+    //A VACUUM puts the store back on Firefox's page size
+    vacuum_store(db_path(&base)).unwrap();
+    assert_eq!(page_size(), 1024);
+}
+
+#[test]
+fn saved_game_digest_matches_for_the_same_world () {
+    let dirs: Vec<TempDir> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+    let bases: Vec<String> = dirs.iter().map(|dir| dir.path().to_str().unwrap().to_string()).collect();
+
+    let level_str: String = serialize_saved_game_sorted(&JSLevel::new(5, HashMap::new(), 128, 1));
+    let other_str: String = serialize_saved_game_sorted(&JSLevel::new(6, HashMap::new(), 128, 1));
+    write_data(bases[0].clone(), [level_str.clone(), serialize_settings(Settings::default())], Origin::parse(WEBSITE).unwrap()).unwrap();
+    write_data(bases[1].clone(), [level_str, serialize_settings(Settings { username: String::from("other"), ..Settings::default() })], Origin::parse(WEBSITE).unwrap()).unwrap();
+    write_data(bases[2].clone(), [other_str, serialize_settings(Settings::default())], Origin::parse(WEBSITE).unwrap()).unwrap();
+
+    let digests: Vec<u64> = bases.iter().map(|base| saved_game_digest(db_path(base)).unwrap()).collect();
+    assert_eq!(digests[0], digests[1]);
+    assert_ne!(digests[0], digests[2]);
+}
+
+#[test]
+fn saved_game_and_settings_read_together () {
+    let (_dir, base) = default_store();
+    let json_strings: [String; 2] = default_json_strings();
+    assert_eq!(read_saved_game_and_settings(db_path(&base)).unwrap(), (Some(json_strings[0].clone()), Some(json_strings[1].clone())));
+
+    let conn: rusqlite::Connection = rusqlite::Connection::open(db_path(&base)).unwrap();
+    conn.execute("DELETE FROM data WHERE key = 'settings'", []).unwrap();
+    assert_eq!(read_saved_game_and_settings(db_path(&base)).unwrap(), (Some(json_strings[0].clone()), None));
+}
+
+#[test]
+fn blank_database_is_not_a_classic_store () {
+    assert!(matches!(read_from_db(String::from(":memory:"), "savedGame"), Err(ClassicJsError::NotAClassicStore(_))));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path: String = dir.path().join("data.sqlite").to_str().unwrap().to_string();
+    std::fs::write(&path, "not an sqlite database, but long enough to have a header of one").unwrap();
+    assert!(matches!(read_all(path), Err(ClassicJsError::NotAClassicStore(_))));
+}
+
+#[test]
+fn missing_store_is_not_created () {
+    let dir = tempfile::tempdir().unwrap();
+    let path: String = dir.path().join("data.sqlite").to_str().unwrap().to_string();
+
+    assert!(matches!(read_database_meta(path.clone()), Err(ClassicJsError::Io(_))));
+    assert!(matches!(read_saved_game(path.clone()), Err(ClassicJsError::Io(_))));
+    assert!(!std::path::Path::new(&path).exists());
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzipped_store_reads_back () {
+    use std::io::Write;
+
+    let (_dir, base) = temp_base();
+
+    let level: JSLevel = JSLevel::new(42, HashMap::new(), 128, 1);
+    let level_str: String = serde_json::to_string(&level).unwrap();
+    write_data(base.clone(), [level_str.clone(), serialize_settings(Settings::default())], Origin::parse(WEBSITE).unwrap()).unwrap();
+
+    let gz_path: String = format!("{}/data.sqlite.gz", base);
+    let mut encoder = flate2::write::GzEncoder::new(std::fs::File::create(&gz_path).unwrap(), flate2::Compression::default());
+    encoder.write_all(&std::fs::read(db_path(&base)).unwrap()).unwrap();
+    encoder.finish().unwrap();
+
+    assert_eq!(read_from_db(gz_path.clone(), "savedGame").unwrap(), level_str);
+    assert_eq!(read_data(gz_path).unwrap().js_level, level);
+}
+
+#[test]
+fn settings_are_read_whatever_the_key_casing () {
+    let (_dir, base) = temp_base();
+
+    let settings: Settings = Settings { username: String::from("cased"), ..Settings::default() };
+    let settings_str: String = serialize_settings(settings.clone());
+    let level_str: String = serialize_saved_game_sorted(&JSLevel::new(1, HashMap::new(), 128, 1));
+
+    let options: WriteOptions = WriteOptions { keys: [String::from("savedGame"), String::from("Settings")], ..WriteOptions::default() };
+    write_data_with_options(base.clone(), [level_str.clone(), settings_str.clone()], Origin::parse(WEBSITE).unwrap(), &options).unwrap();
+
+    assert_eq!(read_settings(db_path(&base)).unwrap(), settings_str);
+    assert_eq!(read_from_db(db_path(&base), "settings").unwrap(), "");
+    assert_eq!(read_data(db_path(&base)).unwrap().settings, settings);
+    assert_eq!(read_saved_game_and_settings(db_path(&base)).unwrap(), (Some(level_str), Some(settings_str)));
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn list_game_origins_finds_gzipped_stores () {
+    use std::io::Write;
+
+    let (_dir, base) = default_store();
+
+    let bytes: Vec<u8> = std::fs::read(db_path(&base)).unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(std::fs::File::create(db_path(&base)).unwrap(), flate2::Compression::default());
+    encoder.write_all(&bytes).unwrap();
+    encoder.finish().unwrap();
+
+    assert_eq!(list_game_origins(base).unwrap(), vec![WEBSITE]);
+}
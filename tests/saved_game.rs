@@ -0,0 +1,322 @@
+use mc_classic_js::{
+    deserialize_saved_game, generate_saved_game_from_seed, generate_saved_game_from_seed_cached, get_tile_map,
+    rebuild_changed_blocks, serialize_saved_game, serialize_saved_game_sorted, tile_map_len, ChangedBlocks, ClassicJsError,
+    JSLevel, Optimization, TileMapCache
+};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+#[test]
+fn deserializes_save_without_version () {
+    let level: JSLevel = deserialize_saved_game(String::from(r#"{"worldSeed":42,"changedBlocks":{"p1_2_3":{"a":1,"bt":5}},"worldSize":128}"#));
+
+    assert_eq!(level.version, 1);
+    assert_eq!(level.worldSeed, 42);
+    assert_eq!(level.worldSize, 128);
+    assert_eq!(level.changedBlocks.get("p1_2_3").unwrap().bt, 5);
+}
+
+#[test]
+fn deserializes_save_without_changed_blocks () {
+    let level: JSLevel = deserialize_saved_game(String::from(r#"{"worldSeed":0,"worldSize":256,"version":1}"#));
+
+    assert!(level.changedBlocks.is_empty());
+}
+
+#[test]
+fn serialize_rejects_mismatched_tile_map () {
+    //A 128 world's tile map with a level claiming a 256 world
+    let level: JSLevel = JSLevel::new(1, HashMap::new(), 256, 1);
+    let tile_map: Vec<u8> = vec![0; 128 * 128 * 64];
+
+    match serialize_saved_game(level, tile_map, 2) {
+        Err(ClassicJsError::TileMapSizeMismatch(expected, len)) => {
+            assert_eq!(expected, 256 * 256 * 64);
+            assert_eq!(len, 128 * 128 * 64);
+        },
+        other => panic!("expected TileMapSizeMismatch, got {:?}", other)
+    }
+}
+
+#[test]
+fn generate_rejects_tile_map_of_no_world_size () {
+    assert!(matches!(generate_saved_game_from_seed(1, vec![0; 100]), Err(ClassicJsError::InvalidWorldSize(1))));
+}
+
+#[test]
+fn generate_infers_a_256_world () {
+    //Generating the world once, the cache hands it back to the generate call
+    let mut cache: TileMapCache = TileMapCache::new();
+    let tile_map: Vec<u8> = cache.get_or_generate(256, 9).to_vec();
+
+    let level: JSLevel = generate_saved_game_from_seed_cached(9, tile_map, &mut cache).unwrap();
+    assert_eq!(level.worldSize, 256);
+    assert!(level.changedBlocks.is_empty());
+}
+
+#[test]
+fn sorted_serialization_hashes_the_same () {
+    let blocks: Vec<(String, (u8, u8))> = (0..50).map(|i| (format!("p{}_{}_{}", i % 7, i % 5, i), (1, i as u8))).collect();
+
+    //The same level built in opposite insertion orders
+    let level1: JSLevel = JSLevel::new(7, blocks.iter().map(|(key, block)| (key.clone(), (*block).into())).collect(), 128, 1);
+    let level2: JSLevel = JSLevel::new(7, blocks.iter().rev().map(|(key, block)| (key.clone(), (*block).into())).collect(), 128, 1);
+
+    let hash = |level: &JSLevel| {
+        let mut hasher: DefaultHasher = DefaultHasher::new();
+        serialize_saved_game_sorted(level).hash(&mut hasher);
+        hasher.finish()
+    };
+
+    assert_eq!(hash(&level1), hash(&level2));
+    assert_eq!(deserialize_saved_game(serialize_saved_game_sorted(&level1)), level1);
+}
+
+#[cfg(feature = "native")]
+#[test]
+fn json_files_round_trip () {
+    use mc_classic_js::{read_saved_game_file, read_settings_file, write_saved_game_file, write_settings_file, Settings};
+
+    let dir = tempfile::tempdir().unwrap();
+    let level_path: String = dir.path().join("savedGame.json").to_str().unwrap().to_string();
+    let settings_path: String = dir.path().join("settings.json").to_str().unwrap().to_string();
+
+    let level: JSLevel = JSLevel::new(42, HashMap::from([(String::from("p3_40_9"), (1, 5).into())]), 128, 1);
+    let settings: Settings = Settings { username: String::from("builder"), ..Settings::default() };
+
+    write_saved_game_file(level_path.clone(), &level).unwrap();
+    write_settings_file(settings_path.clone(), &settings).unwrap();
+
+    assert_eq!(read_saved_game_file(level_path).unwrap(), level);
+    assert_eq!(read_settings_file(settings_path).unwrap(), settings);
+    assert!(matches!(read_saved_game_file(dir.path().join("missing.json").to_str().unwrap().to_string()), Err(ClassicJsError::Io(_))));
+}
+
+#[test]
+fn change_summary_groups_by_region () {
+    let mut changed_blocks: HashMap<String, mc_classic_js::ChangedBlocks> = HashMap::new();
+    for x in 0..3 { changed_blocks.insert(format!("p{}_20_70", x), (1, 2).into()); }
+    changed_blocks.insert(String::from("p100_1_1"), (1, 0).into());
+    changed_blocks.insert(String::from("bogus"), (1, 0).into());
+    let level: JSLevel = JSLevel::new(1, changed_blocks, 128, 1);
+
+    assert_eq!(level.change_summary(), "5 changed blocks in a 128 world\n\
+        3 blocks changed in region (0-64, 16-32, 64-128): mostly rock (2)\n\
+        1 blocks changed in region (64-128, 0-16, 0-64): mostly air (0)\n\
+        1 keys not in the px_y_z form\n");
+}
+
+#[test]
+fn reused_codec_round_trips_many_values () {
+    use mc_classic_js::{compress_value, compress_value_with, decompress_value_with, Codec, Compression};
+
+    let mut codec: Codec = Codec::new();
+    for seed in 0..20 {
+        let json_string: String = serialize_saved_game_sorted(&JSLevel::new(seed, HashMap::new(), 128, 1));
+        let compressed: Vec<u8> = compress_value_with(json_string.as_bytes(), Compression::Snappy, &mut codec).unwrap();
+
+        assert_eq!(compressed, compress_value(json_string.as_bytes(), Compression::Snappy).unwrap());
+        assert_eq!(decompress_value_with(&compressed, Compression::Snappy, &mut codec).unwrap(), json_string.as_bytes());
+    }
+}
+
+#[test]
+fn validate_bindings_lists_unknown_tokens () {
+    use mc_classic_js::{is_valid_key_token, Settings};
+
+    assert!(is_valid_key_token("W") && is_valid_key_token("7") && is_valid_key_token("<enter>"));
+    assert!(!is_valid_key_token("Spaec") && !is_valid_key_token("") && !is_valid_key_token("<spaec>"));
+
+    let settings: Settings = Settings { jump: String::from("Spaec"), ..Settings::default() };
+    assert!(Settings::default().validate_bindings().is_empty());
+    assert_eq!(settings.validate_bindings(), vec![(String::from("jump"), String::from("Spaec"))]);
+}
+
+#[test]
+fn normalize_makes_equivalent_levels_equal () {
+    let mut changed_blocks1: HashMap<String, mc_classic_js::ChangedBlocks> = HashMap::new();
+    changed_blocks1.insert(String::from("p007_02_3"), (1, 4).into());
+    changed_blocks1.insert(String::from("p7_2_3"), (1, 5).into());
+    changed_blocks1.insert(String::from("p+1_0_0"), (1, 6).into());
+    changed_blocks1.insert(String::from("p200_0_0"), (1, 6).into());
+    changed_blocks1.insert(String::from("p1_64_0"), (1, 6).into());
+    changed_blocks1.insert(String::from("bogus"), (1, 6).into());
+    let mut level1: JSLevel = JSLevel::new(1, changed_blocks1, 128, 1);
+
+    let mut changed_blocks2: HashMap<String, mc_classic_js::ChangedBlocks> = HashMap::new();
+    changed_blocks2.insert(String::from("p7_2_3"), (1, 5).into());
+    changed_blocks2.insert(String::from("p1_0_0"), (1, 6).into());
+    let mut level2: JSLevel = JSLevel::new(1, changed_blocks2, 128, 1);
+
+    level1.normalize();
+    level2.normalize();
+
+    assert_eq!(level1, level2);
+    assert_eq!(serialize_saved_game_sorted(&level1), serialize_saved_game_sorted(&level2));
+}
+
+#[test]
+fn stamp_level_skips_blocks_outside_the_base () {
+    use mc_classic_js::{stamp_level, ChangedBlocks};
+
+    let mut changed_blocks: HashMap<String, ChangedBlocks> = HashMap::new();
+    changed_blocks.insert(String::from("p0_0_0"), (1, 4).into());
+    changed_blocks.insert(String::from("p3_1_2"), (1, 5).into());
+    changed_blocks.insert(String::from("p0_10_0"), (1, 6).into());
+    let stamp: JSLevel = JSLevel::new(1, changed_blocks, 128, 1);
+
+    let mut base: JSLevel = JSLevel::new(2, HashMap::new(), 128, 1);
+    assert_eq!(stamp_level(&mut base, &stamp, (125, 55, 10)), 1);
+    assert_eq!(base.changedBlocks.len(), 1);
+    assert_eq!(base.changedBlocks["p125_55_10"], ChangedBlocks::new(1, 4));
+}
+
+#[test]
+fn settings_map_round_trips () {
+    use mc_classic_js::Settings;
+
+    let mut settings: Settings = Settings { music: true, drawDistance: 2, username: String::from("builder"), ..Settings::default() };
+    settings.extra.insert(String::from("sensitivity"), serde_json::json!(0.5));
+    settings.extra.insert(String::from("theme"), serde_json::json!("dark"));
+
+    let map: HashMap<String, String> = settings.to_map();
+    assert_eq!(map["music"], "true");
+    assert_eq!(map["drawDistance"], "2");
+    assert_eq!(map["jump"], "<space>");
+    assert_eq!(map["sensitivity"], "0.5");
+    assert_eq!(map["theme"], "dark");
+    assert_eq!(Settings::from_map(&map).unwrap(), settings);
+
+    let mut bad: HashMap<String, String> = map.clone();
+    bad.insert(String::from("music"), String::from("yes"));
+    assert!(matches!(Settings::from_map(&bad), Err(ClassicJsError::Json(_))));
+    bad = map.clone();
+    bad.remove("username");
+    assert!(matches!(Settings::from_map(&bad), Err(ClassicJsError::Json(_))));
+}
+
+#[test]
+fn auto_optimization_picks_by_change_ratio () {
+    use mc_classic_js::{get_tile_map, tile_map_len, Optimization};
+
+    let level: JSLevel = JSLevel::new(3, HashMap::new(), 128, 1);
+    let auto: u8 = Optimization::Auto.opt();
+
+    //A lightly edited world is diffed
+    let mut tile_map: Vec<u8> = get_tile_map(128, 3);
+    tile_map[0] = 20;
+    assert_eq!(serialize_saved_game(level.clone(), tile_map.clone(), auto).unwrap(), serialize_saved_game(level.clone(), tile_map, 2).unwrap());
+
+    //A world of nothing but glass has every block written
+    let custom: Vec<u8> = vec![20; tile_map_len(128)];
+    assert_eq!(serialize_saved_game(level.clone(), custom.clone(), auto).unwrap(), serialize_saved_game(level, custom, 0).unwrap());
+}
+
+#[test]
+fn serializing_with_a_natural_map_matches () {
+    use mc_classic_js::{clear_generation_cache, get_tile_map, serialize_saved_game_with_natural};
+
+    let natural: Vec<u8> = get_tile_map(128, 11);
+    let mut tile_map: Vec<u8> = natural.clone();
+    tile_map[5] = 20;
+    let level: JSLevel = JSLevel::new(11, HashMap::new(), 128, 1);
+
+    let expected: String = serialize_saved_game_with_natural(level.clone(), tile_map.clone(), &natural, 2).unwrap();
+    assert_eq!(serialize_saved_game(level.clone(), tile_map.clone(), 2).unwrap(), expected);
+    //Again from the generation cache, and after clearing it
+    assert_eq!(serialize_saved_game(level.clone(), tile_map.clone(), 2).unwrap(), expected);
+    clear_generation_cache();
+    assert_eq!(serialize_saved_game(level.clone(), tile_map.clone(), 2).unwrap(), expected);
+
+    assert!(matches!(serialize_saved_game_with_natural(level, tile_map, &natural[1..], 2), Err(ClassicJsError::TileMapSizeMismatch(_, _))));
+}
+
+#[test]
+fn level_header_reads_without_changed_blocks () {
+    use mc_classic_js::read_level_header;
+
+    let mut changed_blocks: HashMap<String, mc_classic_js::ChangedBlocks> = HashMap::new();
+    changed_blocks.insert(String::from("p1_2_3"), (1, 5).into());
+    let level: JSLevel = JSLevel::new(-42, changed_blocks, 256, 1);
+
+    assert_eq!(read_level_header(&serialize_saved_game_sorted(&level)).unwrap(), (-42, 256, 1));
+    assert_eq!(read_level_header(r#"{"worldSeed":7,"changedBlocks":{},"worldSize":128}"#).unwrap(), (7, 128, 1));
+    assert!(matches!(read_level_header(r#"{"worldSize":128}"#), Err(ClassicJsError::Json(_))));
+}
+
+#[test]
+fn diff_levels_lists_changes_in_order () {
+    use mc_classic_js::{diff_levels, ChangedBlocks, ChangedPosition, LevelDiff};
+
+    let mut changed_blocks_a: HashMap<String, ChangedBlocks> = HashMap::new();
+    changed_blocks_a.insert(String::from("p1_5_0"), (1, 4).into());
+    changed_blocks_a.insert(String::from("p2_1_0"), (1, 4).into());
+    changed_blocks_a.insert(String::from("p07_2_3"), (1, 5).into());
+    changed_blocks_a.insert(String::from("p9_9_9"), (1, 6).into());
+    let mut changed_blocks_b: HashMap<String, ChangedBlocks> = HashMap::new();
+    changed_blocks_b.insert(String::from("p7_2_3"), (1, 5).into());
+    changed_blocks_b.insert(String::from("p9_9_9"), (1, 20).into());
+    changed_blocks_b.insert(String::from("p0_0_0"), (1, 1).into());
+    let a: JSLevel = JSLevel::new(1, changed_blocks_a, 128, 1);
+    let b: JSLevel = JSLevel::new(1, changed_blocks_b, 128, 1);
+
+    let diff: LevelDiff = diff_levels(&a, &b);
+    assert_eq!(diff.only_in_a, vec![(ChangedPosition::new(2, 1, 0), ChangedBlocks::new(1, 4)), (ChangedPosition::new(1, 5, 0), ChangedBlocks::new(1, 4))]);
+    assert_eq!(diff.only_in_b, vec![(ChangedPosition::new(0, 0, 0), ChangedBlocks::new(1, 1))]);
+    assert_eq!(diff.differing, vec![(ChangedPosition::new(9, 9, 9), 6, 20)]);
+    assert!(diff_levels(&a, &a).is_empty());
+}
+
+#[test]
+fn forced_blocks_are_always_written () {
+    use mc_classic_js::{get_tile_map, serialize_saved_game_with_forced};
+    use std::collections::HashSet;
+
+    let tile_map: Vec<u8> = get_tile_map(128, 4);
+    let level: JSLevel = JSLevel::new(4, HashMap::new(), 128, 1);
+    let force_include: HashSet<(i32, i32, i32)> = [(1, 2, 3), (500, 0, 0)].into_iter().collect();
+
+    let forced: JSLevel = deserialize_saved_game(serialize_saved_game_with_forced(level.clone(), tile_map.clone(), 2, &force_include).unwrap());
+    assert_eq!(forced.changedBlocks.len(), 1);
+    assert_eq!(forced.changedBlocks["p1_2_3"].a, 0);
+    assert_eq!(forced.changedBlocks["p1_2_3"].bt, tile_map[(2 * 128 * 128) + (3 * 128) + 1]);
+
+    assert_eq!(serialize_saved_game_with_forced(level.clone(), tile_map.clone(), 2, &HashSet::new()).unwrap(), serialize_saved_game(level, tile_map, 2).unwrap());
+}
+
+#[test]
+fn rebuild_changed_blocks_recomputes_from_the_tile_map () {
+    let mut tile_map: Vec<u8> = get_tile_map(128, 13);
+    //x 3, y 0, z 0 and x 1, y 2, z 5
+    tile_map[3] = tile_map[3].wrapping_add(1);
+    let index: usize = 2 * 128 * 128 + 5 * 128 + 1;
+    tile_map[index] = 49;
+
+    let changed: HashMap<String, ChangedBlocks> = rebuild_changed_blocks(&tile_map, 13, 128, Optimization::DiffOnly).unwrap();
+    assert_eq!(changed.len(), 2);
+    assert_eq!(changed["p3_0_0"], ChangedBlocks::new(1, tile_map[3]));
+    assert_eq!(changed["p1_2_5"], ChangedBlocks::new(1, 49));
+
+    assert_eq!(rebuild_changed_blocks(&tile_map, 13, 128, Optimization::ChangedOrDiff).unwrap(), changed);
+    assert_eq!(rebuild_changed_blocks(&tile_map, 13, 128, Optimization::Auto).unwrap(), changed);
+
+    let all: HashMap<String, ChangedBlocks> = rebuild_changed_blocks(&tile_map, 13, 128, Optimization::All).unwrap();
+    assert_eq!(all.len(), tile_map_len(128));
+    assert_eq!(all["p0_0_0"], ChangedBlocks::new(0, tile_map[0]));
+    assert_eq!(all["p1_2_5"], ChangedBlocks::new(1, 49));
+
+    tile_map.pop();
+    assert!(matches!(rebuild_changed_blocks(&tile_map, 13, 128, Optimization::DiffOnly), Err(ClassicJsError::TileMapSizeMismatch(_, _))));
+}
+
+#[test]
+fn generated_level_equals_its_json () {
+    let level: JSLevel = generate_saved_game_from_seed(13, get_tile_map(128, 13)).unwrap();
+    assert!(level.spawn.is_some());
+
+    let read_back: JSLevel = deserialize_saved_game(serialize_saved_game_sorted(&level));
+    assert_eq!(read_back.spawn, None);
+    assert_eq!(read_back, level);
+}
@@ -0,0 +1,208 @@
+use mc_classic_js::{assert_tile_map_ordering, block_at, classify_changes, compare_to_reference, count_changes, generate_layers, generate_surface_only, get_tile_map, index_to_xyz, is_natural_block, natural_block_at, natural_block_at_cached, reorder_from_java, recompute_surface, reorder_to_java, resize_world, rle_decode, rle_encode, surface_height, tile_map_len, xyz_to_index, Anchor, ChangeKind, ChangedPosition, ClassicJsError, CompareReport, TileMapCache};
+
+#[test]
+fn java_order_round_trips () {
+    let world_size: i32 = 128;
+    let tile_map: Vec<u8> = (0..tile_map_len(world_size)).map(|i| (i % 251) as u8).collect();
+
+    let java: Vec<u8> = reorder_to_java(&tile_map, world_size);
+
+    //Block x 5, y 10, z 20 sits at (y * length + z) * width + x in the Java order
+    let (x, y, z): (usize, usize, usize) = (5, 10, 20);
+    let tile_index: usize = (y * 128 * 128) + (z * 128) + x;
+    assert_eq!(java[(y * 128 + z) * 128 + x], tile_map[tile_index]);
+
+    assert_eq!(reorder_from_java(&java, world_size), tile_map);
+}
+
+#[test]
+fn layers_concatenate_to_the_tile_map () {
+    let layers: Vec<Vec<u8>> = generate_layers(128, 42).collect();
+
+    assert_eq!(layers.len(), 64);
+    assert_eq!(layers.concat(), get_tile_map(128, 42));
+}
+
+#[test]
+fn changes_are_classified () {
+    let world_size: i32 = 128;
+    let natural: Vec<u8> = (0..tile_map_len(world_size)).map(|i| if i < 128 * 128 { 2 } else { 0 }).collect();
+    let mut current: Vec<u8> = natural.clone();
+    current[5] = 0; //x 5, y 0, z 0
+    current[128 + 7] = 3; //x 7, y 0, z 1
+    current[128 * 128 * 2 + 9] = 1; //x 9, y 2, z 0
+
+    assert_eq!(classify_changes(&current, &natural, world_size).unwrap(), vec![
+        (ChangedPosition::new(5, 0, 0), ChangeKind::Removed),
+        (ChangedPosition::new(7, 0, 1), ChangeKind::Swapped),
+        (ChangedPosition::new(9, 2, 0), ChangeKind::Placed)
+    ]);
+
+    current.pop();
+    assert!(matches!(classify_changes(&current, &natural, world_size), Err(ClassicJsError::TileMapSizeMismatch(_, _))));
+}
+
+#[test]
+fn surface_only_is_the_top_of_the_tile_map () {
+    let tile_map: Vec<u8> = get_tile_map(128, 42);
+
+    assert_eq!(generate_surface_only(128, 42, 8), tile_map[56 * 128 * 128..]);
+    assert!(generate_surface_only(128, 42, 0).is_empty());
+}
+
+#[test]
+fn block_at_checks_bounds () {
+    let world_size: i32 = 128;
+    let tile_map: Vec<u8> = (0..tile_map_len(world_size)).map(|i| (i % 251) as u8).collect();
+
+    assert_eq!(block_at(&tile_map, world_size, 5, 10, 20), Some(tile_map[(10 * 128 * 128) + (20 * 128) + 5]));
+    assert_eq!(block_at(&tile_map, world_size, 127, 63, 127), tile_map.last().copied());
+    assert_eq!(block_at(&tile_map, world_size, 128, 0, 0), None);
+    assert_eq!(block_at(&tile_map, world_size, 0, 64, 0), None);
+    assert_eq!(block_at(&tile_map, world_size, 0, 0, -1), None);
+    assert_eq!(block_at(&tile_map[..10], world_size, 0, 1, 0), None);
+}
+
+#[test]
+fn natural_block_at_matches_the_tile_map () {
+    let tile_map: Vec<u8> = get_tile_map(128, 42);
+    let mut cache: TileMapCache = TileMapCache::new();
+
+    for (x, y, z) in [(0, 0, 0), (64, 30, 64), (127, 63, 5)] {
+        assert_eq!(natural_block_at_cached(128, 42, x, y, z, &mut cache), block_at(&tile_map, 128, x, y, z));
+    }
+    assert_eq!(cache.len(), 1);
+    assert_eq!(natural_block_at(128, 42, 10, 20, 30), block_at(&tile_map, 128, 10, 20, 30));
+    assert_eq!(natural_block_at(128, 42, 128, 0, 0), None);
+    assert_eq!(natural_block_at(100, 42, 0, 0, 0), None);
+}
+
+#[test]
+fn compare_to_reference_lists_mismatches () {
+    let mut reference: Vec<u8> = get_tile_map(128, 42);
+    assert!(compare_to_reference(128, 42, &reference).matches());
+
+    let generated: u8 = reference[(10 * 128 * 128) + (20 * 128) + 5];
+    reference[(10 * 128 * 128) + (20 * 128) + 5] = generated.wrapping_add(1);
+    let report: CompareReport = compare_to_reference(128, 42, &reference);
+    assert_eq!(report.differing, 1);
+    assert_eq!(report.first_mismatches, vec![(ChangedPosition::new(5, 10, 20), generated, generated.wrapping_add(1))]);
+
+    let report: CompareReport = compare_to_reference(128, 42, &reference[..100]);
+    assert_eq!(report.len_mismatch, Some((tile_map_len(128), 100)));
+    assert_eq!(report.differing, tile_map_len(128) - 100);
+}
+
+#[test]
+fn rle_round_trips () {
+    let tile_map: Vec<u8> = get_tile_map(128, 42);
+    let encoded: Vec<u8> = rle_encode(&tile_map);
+
+    assert!(encoded.len() < tile_map.len());
+    assert_eq!(rle_decode(&encoded, tile_map.len()).unwrap(), tile_map);
+
+    //Runs longer than 255 are split
+    assert_eq!(rle_encode(&[7; 300]), vec![255, 7, 45, 7]);
+    assert!(rle_encode(&[]).is_empty());
+
+    assert!(matches!(rle_decode(&encoded, tile_map.len() - 1), Err(ClassicJsError::TileMapSizeMismatch(_, _))));
+    assert!(matches!(rle_decode(&[1, 2, 3], 1), Err(ClassicJsError::InvalidRle(_))));
+    assert!(matches!(rle_decode(&[0, 2], 0), Err(ClassicJsError::InvalidRle(_))));
+}
+
+#[test]
+fn tile_map_arc_is_shared_between_threads () {
+    use mc_classic_js::get_tile_map_arc;
+    use std::sync::Arc;
+
+    let natural: Arc<Vec<u8>> = get_tile_map_arc(128, 9);
+
+    let handles: Vec<std::thread::JoinHandle<usize>> = (0..2).map(|_| {
+        let natural: Arc<Vec<u8>> = Arc::clone(&natural);
+        std::thread::spawn(move || natural.iter().filter(|bt| **bt == 0).count())
+    }).collect();
+    let tile_map: Vec<u8> = get_tile_map(128, 9);
+    assert_eq!(*natural, tile_map);
+    let air: usize = tile_map.iter().filter(|bt| **bt == 0).count();
+    for handle in handles { assert_eq!(handle.join().unwrap(), air) }
+}
+
+#[test]
+fn count_changes_counts_differing_blocks () {
+    let mut tile_map: Vec<u8> = get_tile_map(128, 13);
+    assert_eq!(count_changes(&tile_map, 128, 13), 0);
+
+    for i in [0, 1000, 50000] { tile_map[i] = tile_map[i].wrapping_add(1) }
+    assert_eq!(count_changes(&tile_map, 128, 13), 3);
+
+    //Blocks a short tile map lacks count as changed
+    tile_map.truncate(tile_map.len() - 10);
+    assert_eq!(count_changes(&tile_map, 128, 13), 13);
+}
+
+#[test]
+fn tile_map_ordering_is_x_z_y () {
+    assert_tile_map_ordering();
+
+    assert_eq!(xyz_to_index(128, 5, 2, 7), Some(2 * 128 * 128 + 7 * 128 + 5));
+    assert_eq!(index_to_xyz(128, 2 * 128 * 128 + 7 * 128 + 5), Some((5, 2, 7)));
+    assert_eq!(xyz_to_index(128, 0, 64, 0), None);
+    assert_eq!(xyz_to_index(128, -1, 0, 0), None);
+}
+
+#[test]
+fn resize_world_checks_its_sizes () {
+    let tile_map: Vec<u8> = vec![1; tile_map_len(128)];
+
+    let resized: Vec<u8> = resize_world(&tile_map, 128, 256, Anchor::Corner).unwrap();
+    assert_eq!(resized.len(), tile_map_len(256));
+    assert_eq!(block_at(&resized, 256, 127, 0, 127), Some(1));
+    assert_eq!(block_at(&resized, 256, 128, 0, 0), Some(0));
+
+    assert!(matches!(resize_world(&tile_map, 128, 100, Anchor::Center), Err(ClassicJsError::InvalidWorldSize(100))));
+    assert!(matches!(resize_world(&tile_map[1..], 128, 256, Anchor::Center), Err(ClassicJsError::TileMapSizeMismatch(_, _))));
+}
+
+#[test]
+fn recompute_surface_turns_shaded_grass_to_dirt () {
+    let world_size: i32 = 128;
+    let mut tile_map: Vec<u8> = vec![0; tile_map_len(world_size)];
+    let grass: usize = xyz_to_index(world_size, 4, 10, 4).unwrap();
+    tile_map[grass] = 1;
+    tile_map[xyz_to_index(world_size, 4, 12, 4).unwrap()] = 2;
+
+    //A short tile map is left as it is
+    let mut short: Vec<u8> = tile_map[1..].to_vec();
+    recompute_surface(&mut short, world_size);
+    assert_eq!(short, tile_map[1..]);
+
+    recompute_surface(&mut tile_map, world_size);
+    assert_eq!(tile_map[grass], 3);
+}
+
+#[test]
+fn surface_height_is_0_outside_the_world () {
+    let world_size: i32 = 128;
+    let mut tile_map: Vec<u8> = vec![0; tile_map_len(world_size)];
+    tile_map[xyz_to_index(world_size, 3, 20, 4).unwrap()] = 2;
+
+    assert_eq!(surface_height(&tile_map, world_size, 3, 4), 21);
+    assert_eq!(surface_height(&tile_map, world_size, 5, 5), 0);
+    assert_eq!(surface_height(&tile_map, world_size, -1, 4), 0);
+    assert_eq!(surface_height(&tile_map, world_size, 3, 128), 0);
+    assert_eq!(surface_height(&tile_map[..10], world_size, 3, 4), 0);
+}
+
+#[test]
+fn is_natural_block_is_false_outside_the_world () {
+    let world_size: i32 = 128;
+    let natural: Vec<u8> = vec![2; tile_map_len(world_size)];
+    let mut current: Vec<u8> = natural.clone();
+    current[xyz_to_index(world_size, 1, 2, 3).unwrap()] = 0;
+
+    assert!(is_natural_block(&current, &natural, 0, 0, 0, world_size));
+    assert!(!is_natural_block(&current, &natural, 1, 2, 3, world_size));
+    assert!(!is_natural_block(&current, &natural, -1, 0, 0, world_size));
+    assert!(!is_natural_block(&current, &natural, 0, 64, 0, world_size));
+    assert!(!is_natural_block(&current[..10], &natural, 0, 1, 0, world_size));
+}